@@ -1,24 +1,50 @@
 pub mod trader;
+pub mod markets;
+pub mod simulation;
 
 #[cfg(test)]
 mod tests {
     
    
 
+    use std::cell::RefCell;
     use std::rc::Rc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
 
     use bfb::bfb_market::Bfb;
     use bose::market::BoseMarket;
     
     
-    use market_common::good::good_kind::GoodKind::{YUAN};
+    use market_common::good::good::Good;
+    use market_common::good::good_kind::GoodKind::{EUR, USD, YEN, YUAN};
 
 
-    use market_common::market::Market;
-    use market_common::subscribe_each_other;
+    use market_common::market::{BuyError, LockBuyError, LockSellError, Market};
 
+    use crate::markets::historical_market::HistoricalMarket;
+    use crate::markets::faulty_market::{FaultyCall, FaultyMarket, Injection, Trigger};
+    use crate::markets::recording_market::RecordingMarket;
+    use crate::markets::replay_market::ReplayMarket;
+    use crate::markets::synthetic_market::{PriceProcess, SyntheticMarket};
+    use crate::markets::threaded_market::ThreadedMarket;
+    use crate::simulation::{MarketSetup, SimulationBuilder};
+    use crate::simulation::monte_carlo::MonteCarlo;
+    use crate::simulation::tournament::{paired_t_test, Tournament};
+    use crate::simulation::environment::{Action, TradingEnvironment};
+    use crate::simulation::optimizer::{Objective, Optimizer, ParameterSpace, Params};
     use crate::trader::MarketKind::{BFB, BOSE};
-    use crate::trader::{MarketKind, Trader};
+    use crate::trader::{seed_file, MarketKind, Trader};
+    use crate::trader::trader_errors::{TraderDemandError, TraderSupplyError};
+    use crate::trader::trader_sync::{run_in_parallel, SyncTrader};
+    use crate::trader::trader_risk::{RiskLimits, RiskViolation};
+    use crate::trader::trader_orders::OrderOutcome;
+    use crate::trader::trader_rebalance::RebalancePolicy;
+    use crate::trader::trader_execution::ParentOrder;
+    use crate::trader::trader_negotiation::Negotiation;
+    use crate::trader::trader_costs::{FeeSchedule, TradeSide, TransactionCharges};
+    use crate::trader::trader_paper::LinearImpact;
+    use crate::trader::trader_run::{FailurePolicy, RunResult, StopCondition, StopReason};
 
     #[test]
     fn trader_example() {
@@ -54,12 +80,849 @@ mod tests {
             .with_initial_money(10001.0)
             .with_good(YUAN, 10000000.0);
 
-        //todo: move this macro inside the trader definition.
-        subscribe_each_other!(bose, bfb, tase);
+        assert!(trader.is_subscribed(BOSE, BFB) && trader.is_subscribed(BFB, BOSE));
+        assert_eq!(trader.subscriptions().len(), 6);
 
         trader.set_strategy(closure);
 
         //trader.run(1);
         println!("{:#?}", trader);
     }
+
+    #[test]
+    fn removed_market_stops_notifying_its_peers() {
+        let path = std::env::temp_dir().join("trader_removed_market_test.jsonl");
+        let peer = RecordingMarket::wrap(SyntheticMarket::new(2).with_default_goods(1000.0).build(), &path).unwrap();
+        let removed = SyntheticMarket::new(1).with_default_goods(1000.0).build();
+        let mut trader = Trader::new().without_output_file()
+            .with_market(BFB, peer.clone())
+            .with_market(BOSE, removed.clone());
+        let heard = || std::fs::read_to_string(&path).unwrap().lines().filter(|l| l.contains("\"call\":\"on_event\"")).count();
+
+        trader.buy(BOSE, USD, 10.0).unwrap();
+        let before = heard();
+        assert!(before > 0);
+
+        assert!(trader.remove_market(BOSE).is_some());
+        assert!(!trader.is_subscribed(BOSE, BFB) && !trader.is_subscribed(BFB, BOSE));
+        assert_eq!(trader.data.len(), 1);
+        assert_eq!(trader.data_index(BFB), Some(0));
+
+        //someone else trades on the detached market: BFB doesn't hear about it
+        let mut cash = Good::new(EUR, 100.0);
+        let token = removed.borrow_mut().lock_buy(USD, 1.0, 10.0, "someone else".to_string()).unwrap();
+        removed.borrow_mut().buy(token, &mut cash).unwrap();
+        assert_eq!(heard(), before);
+
+        //attached again: one data entry per market, and BFB hears it again
+        trader.add_market(BOSE, removed.clone());
+        assert_eq!(trader.data.len(), 2);
+        trader.buy(BOSE, USD, 10.0).unwrap();
+        assert!(heard() > before);
+    }
+
+    #[test]
+    fn event_feed_tells_own_trades_from_the_others() {
+        let market = SyntheticMarket::new(4).with_default_goods(1000.0).build();
+        let mut trader = Trader::new().without_output_file()
+            .with_market(BOSE, market.clone())
+            .with_event_feed();
+
+        trader.buy(BOSE, USD, 10.0).unwrap();
+        let own = trader.event_feed().len();
+        assert!(own > 0);
+
+        //somebody else trades on the same market
+        let mut cash = Good::new(EUR, 100.0);
+        let token = market.borrow_mut().lock_buy(USD, 20.0, 50.0, "someone else".to_string()).unwrap();
+        market.borrow_mut().buy(token, &mut cash).unwrap();
+
+        let feed = trader.event_feed();
+        assert_eq!(feed.own().count(), own);
+        assert!(feed.own().all(|e| e.trader.as_deref() == Some("TASE Trader")));
+        assert!(feed.others().count() > 0);
+        assert!(feed.others().all(|e| e.trader.is_none()));
+        assert!(feed.large_trades(0.0).all(|e| !e.is_own() && e.quantity == 20.0));
+    }
+
+    #[test]
+    fn liquidation_retries_a_market_that_refused_a_chunk() {
+        let best = FaultyMarket::wrap(SyntheticMarket::new(1).with_default_goods(1000.0).build())
+            .fail_lock_sell(Trigger::AtSteps(vec![0]), || LockSellError::MaxAllowedLocksReached)
+            .build();
+        let mut trader = Trader::new()
+            .with_market(BOSE, best.clone())
+            .with_market(BFB, SyntheticMarket::new(1).with_default_goods(1000.0).with_spread(0.2).build())
+            .with_good(USD, 100.0);
+
+        let report = trader.bailout();
+        assert!(report.is_complete());
+        assert_eq!(trader.get_owned_good_qty(USD), 0.0);
+        assert_eq!(best.borrow().injections().len(), 1);
+        //BFB only got the chunk that BOSE refused: BOSE was asked again for the next one
+        assert_eq!(report.sales.iter().filter(|s| s.market == BFB).count(), 1);
+        assert!(report.sales.iter().filter(|s| s.market == BOSE).count() > 1);
+    }
+
+    #[test]
+    fn liquidate_to_stops_at_the_target() {
+        let mut trader = Trader::new()
+            .with_market(BOSE, SyntheticMarket::new(1).with_default_goods(1000.0).build())
+            .with_good(USD, 100.0);
+
+        let report = trader.liquidate_to(1020.0);
+        assert!(report.is_complete());
+        assert!(report.cash_after >= 1020.0);
+        assert!(trader.get_owned_good_qty(USD) > 50.0);
+    }
+
+    #[test]
+    fn quotes_rank_markets_and_report_when_none_fits() {
+        let trader = Trader::new()
+            .with_market(BOSE, SyntheticMarket::new(1).with_default_goods(1000.0).build())
+            .with_market(BFB, SyntheticMarket::new(1).with_default_goods(1000.0).with_spread(0.2).build());
+
+        let snapshot = trader.quote_snapshot(USD, 10.0);
+        assert_eq!(snapshot.supplier_ranking, vec![BOSE, BFB]);
+        assert_eq!(snapshot.buyer_ranking, vec![BOSE, BFB]);
+        assert_eq!(trader.cheapest_supplier_for(USD, 10.0), Some(BOSE));
+        assert_eq!(trader.best_buyer_for(USD, 10.0), Some(BOSE));
+
+        //more than any market owns, more than any market can pay
+        assert_eq!(trader.cheapest_supplier_for(USD, 5000.0), None);
+        assert_eq!(trader.best_buyer_for(USD, 1_000_000.0), None);
+        assert_eq!(Trader::new().best_buyer(USD), None);
+    }
+
+    #[test]
+    fn sizing_respects_budgets_and_average_prices() {
+        let mut trader = Trader::new().without_output_file()
+            .with_market(BOSE, SyntheticMarket::new(1).with_default_goods(1000.0).build())
+            .with_cost_model(FeeSchedule::new().with_fixed_fee(3.6));
+
+        //the fixed fee makes small orders too dear: only a middle range is under the price, and the largest
+        //quantity of it is past the first midpoint a plain bisection would try
+        let qty = trader.max_buy_qty_under_avg_price(BOSE, USD, 0.9825);
+        assert!(qty > 300.0 && qty < 500.0);
+        assert!((trader.get_supply_price_qt(BOSE, USD, qty).unwrap() + 3.6) / qty <= 0.9826);
+
+        let bought = trader.buy_with_budget(BOSE, USD, 100.0).unwrap();
+        assert!(trader.get_owned_good_qty(EUR) >= 900.0 - 1e-3);
+        assert!(bought > 90.0);
+    }
+
+    #[test]
+    fn sell_for_amount_tells_who_is_short() {
+        let mut trader = Trader::new().without_output_file()
+            .with_good(USD, 100.0)
+            .with_market(BOSE, SyntheticMarket::new(1).with_default_goods(1000.0).with_budget(5.0).build());
+
+        //the trader's dollars would be enough, the market's euros are not
+        assert_eq!(trader.sell_for_amount(BOSE, USD, 50.0), Err(TraderDemandError::MarketInsufficientFunds));
+        assert!(trader.sell_for_amount(BOSE, USD, 3.0).unwrap() >= 3.0);
+        assert_eq!(trader.sell_for_amount(BOSE, USD, 500.0), Err(TraderDemandError::TraderInsufficientGoods));
+    }
+
+    #[test]
+    fn fees_and_rebates_are_charged_apart_from_the_trade() {
+        let mut trader = Trader::new()
+            .with_market(BOSE, SyntheticMarket::new(1).with_default_goods(1000.0).build())
+            .with_cost_model(FeeSchedule::new().with_fixed_fee(1.0).with_proportional_fee(0.01).with_rebate(0.005));
+        let price = trader.get_supply_price_qt(BOSE, USD, 100.0).unwrap();
+        let euros = trader.get_owned_good_qty(EUR);
+
+        trader.buy(BOSE, USD, 100.0).unwrap();
+
+        let charges = trader.charges();
+        assert_eq!(charges.records.len(), 1);
+        assert!((charges.total_fees() - (1.0 + price * 0.01)).abs() < 1e-3);
+        assert!((charges.total_rebates() - price * 0.005).abs() < 1e-3);
+        assert!((euros - price - charges.net() - trader.get_owned_good_qty(EUR)).abs() < 1e-3);
+
+        //the amazingness only pays once it is turned into the rebate
+        let plain = Trader::new_super_duper_amazing_trader(10.0);
+        assert_eq!(plain.estimate_charges(BOSE, USD, TradeSide::Buy, 100.0).net(), 0.0);
+        let amazing = Trader::new_super_duper_amazing_trader(10.0).with_amazingness_rebate();
+        assert!(amazing.estimate_charges(BOSE, USD, TradeSide::Buy, 100.0).rebate > 0.0);
+    }
+
+    #[test]
+    fn unpaid_fees_are_recorded_as_a_shortfall() {
+        let mut trader = Trader::new().with_good(EUR, 3.0);
+        let charged = trader.settle_charges(BOSE, USD, TradeSide::Buy, 100.0, TransactionCharges { fixed_fee: 5.0, ..Default::default() });
+
+        assert_eq!(charged, 3.0);
+        assert_eq!(trader.get_owned_good_qty(EUR), 0.0);
+        assert_eq!(trader.charges().total_shortfall(), 2.0);
+    }
+
+    #[test]
+    fn sync_trader_trades_on_a_threaded_market() {
+        let market = ThreadedMarket::spawn(|| SyntheticMarket::new(3).with_default_goods(1000.0).build()).shared();
+        let mut trader: SyncTrader = SyncTrader::new().with_market(BOSE, market);
+
+        let price = trader.get_supply_price_qt(BOSE, USD, 10.0).unwrap();
+        assert_eq!(trader.buy(BOSE, USD, 10.0), Ok(10.0));
+        assert!((trader.get_owned_good_qty(EUR) - (1000.0 - price)).abs() < 1e-3);
+        assert!(trader.sell(BOSE, USD, 10.0).unwrap() > 0.0);
+        assert_eq!(trader.get_owned_good_qty(USD), 0.0);
+
+        assert_eq!(trader.get_demand_price_qt(BOSE, USD, -1.0), Err(TraderDemandError::MarketRejectedOrder));
+        assert_eq!(trader.parallel_quote_snapshot(USD, 10.0), trader.quote_snapshot(USD, 10.0));
+        assert_eq!(trader.cheapest_supplier(USD), Some(BOSE));
+    }
+
+    #[test]
+    fn threaded_market_sits_next_to_the_others() {
+        let threaded: Rc<RefCell<dyn Market>> = Rc::new(RefCell::new(ThreadedMarket::spawn(|| SyntheticMarket::new(3).with_default_goods(1000.0).build())));
+        let mut trader = Trader::new().without_output_file()
+            .with_market(BOSE, SyntheticMarket::new(1).with_default_goods(1000.0).build())
+            .with_market(BFB, threaded);
+
+        assert_eq!(trader.buy(BFB, USD, 10.0), Ok(10.0));
+        assert_eq!(trader.buy(BOSE, USD, 10.0), Ok(10.0));
+    }
+
+    #[test]
+    fn run_in_parallel_keeps_the_seed_order() {
+        //more seeds than worker threads
+        let seeds: Vec<u64> = (0..200).collect();
+        let doubled = run_in_parallel(&seeds, |seed| seed * 2);
+        assert_eq!(doubled, seeds.iter().map(|s| s * 2).collect::<Vec<_>>());
+        assert!(run_in_parallel(&[], |seed| seed).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "seed 2")]
+    fn run_in_parallel_passes_panics_on() {
+        run_in_parallel(&[1, 2, 3], |seed| if seed == 2 { panic!("seed 2") } else { seed });
+    }
+
+    #[test]
+    fn paper_trades_stay_in_the_paper_ledger() {
+        let mut trader = Trader::new().without_output_file().with_initial_money(50.0)
+            .with_market(BOSE, SyntheticMarket::new(5).with_default_goods(1000.0).build())
+            .with_cost_model(FeeSchedule::new().with_fixed_fee(1.0))
+            .with_risk_limits(RiskLimits::new());
+        trader.start_paper_trading(LinearImpact { rate: 0.001 });
+
+        assert_eq!(trader.buy(BOSE, USD, 100.0), Err(TraderSupplyError::TraderInsufficientFunds));
+        let quoted = trader.get_supply_price_qt(BOSE, USD, 10.0).unwrap();
+        assert_eq!(trader.buy(BOSE, USD, 10.0), Ok(10.0));
+
+        let report = trader.paper_report().unwrap();
+        assert_eq!(report.fills.len(), 1);
+        //the rejected order didn't count as traded volume
+        let fill_price = report.fills[0].fill_price;
+        assert!((fill_price - quoted * (1.0 + 0.001 * 5.0)).abs() < 1e-4);
+        assert!((trader.get_owned_good_qty(EUR) - (50.0 - fill_price - 1.0)).abs() < 1e-3);
+        //the fees are paper fees, and the visualizer only sees the real holdings
+        assert_eq!(report.charges.total_fees(), 1.0);
+        assert!(trader.charges().records.is_empty());
+        assert!(!trader.liquidity[&EUR].is_empty() && trader.liquidity[&EUR].iter().all(|q| *q == 50.0));
+        //the risk limits see the price the ledger settled, as they would live
+        assert_eq!(trader.market_exposure(BOSE), fill_price);
+
+        let report = trader.stop_paper_trading().unwrap();
+        assert_eq!(report.position_changes[&USD], 10.0);
+        assert!(!trader.is_paper_trading());
+        assert_eq!(trader.get_owned_good_qty(EUR), 50.0);
+        assert_eq!(trader.get_owned_good_qty(USD), 0.0);
+    }
+
+    #[test]
+    fn recorded_market_replays_the_same_run() {
+        let path = std::env::temp_dir().join("trader_recording_test.jsonl");
+
+        let strategy = |trader : &mut Trader| {
+            let _ = trader.buy(BOSE, YUAN, 10.0);
+            trader.wait();
+            let _ = trader.sell(BOSE, YUAN, 5.0);
+        };
+
+        let recorded = RecordingMarket::wrap(BoseMarket::new_random(), &path).unwrap();
+        let mut trader = Trader::new().with_market(BOSE, recorded);
+        trader.set_strategy(strategy);
+        trader.run(1);
+        let recorded_capital = trader.get_capital();
+
+        let mut replayed = Trader::new().with_market(BOSE, ReplayMarket::load(&path).unwrap());
+        replayed.set_strategy(strategy);
+        replayed.run(1);
+
+        assert_eq!(recorded_capital, replayed.get_capital());
+    }
+
+    #[test]
+    fn recorded_markets_replay_what_they_told_each_other() {
+        let dir = std::env::temp_dir();
+        let (bose_path, bfb_path) = (dir.join("trader_recording_bose_test.jsonl"), dir.join("trader_recording_bfb_test.jsonl"));
+
+        //BFB hears about the BOSE trades, and the other way around
+        let strategy = |trader : &mut Trader| {
+            let _ = trader.buy(BOSE, YUAN, 10.0);
+            let _ = trader.get_supply_price_qt(BFB, YUAN, 10.0);
+            let _ = trader.sell(BFB, YUAN, 5.0);
+            trader.wait();
+        };
+
+        let bose = RecordingMarket::wrap(BoseMarket::new_random(), &bose_path).unwrap();
+        let bfb = RecordingMarket::wrap(Bfb::new_random(), &bfb_path).unwrap();
+        let mut trader = Trader::new().without_output_file().with_market(BOSE, bose.clone()).with_market(BFB, bfb.clone());
+        trader.set_strategy(strategy);
+        trader.run(2);
+        let recorded_capital = trader.get_capital();
+        assert!(bose.borrow().status().is_ok());
+        assert!(bfb.borrow().status().is_ok());
+
+        let bose_replay = Rc::new(RefCell::new(ReplayMarket::from_file(&bose_path).unwrap()));
+        let bfb_replay = Rc::new(RefCell::new(ReplayMarket::from_file(&bfb_path).unwrap()));
+        let mut replayed = Trader::new().without_output_file().with_market(BOSE, bose_replay.clone()).with_market(BFB, bfb_replay.clone());
+        replayed.set_strategy(strategy);
+        replayed.run(2);
+
+        assert_eq!(recorded_capital, replayed.get_capital());
+        assert_eq!(bose_replay.borrow().remaining_calls(), 0);
+        assert_eq!(bfb_replay.borrow().remaining_calls(), 0);
+    }
+
+    #[test]
+    fn visualizer_data_follows_the_attached_markets() {
+        let mut trader = Trader::new().without_output_file()
+            .with_market(BOSE, SyntheticMarket::new(1).with_default_goods(1000.0).build());
+        trader.wait_for(2);
+
+        assert_eq!(trader.data_index(BOSE), Some(0));
+        assert_eq!(trader.data_index(BFB), None);
+        assert_eq!(trader.data[0][0][&USD].len(), 2);
+        assert_eq!(trader.data[0][2][&EUR].len(), 2);
+    }
+
+    #[test]
+    fn historical_market_skips_the_quotes_the_trader_could_not_get() {
+        //the synthetic market only trades USD: save_data writes the INFINITY placeholder for YEN and YUAN
+        let path = std::env::temp_dir().join("trader_unquoted_visualizer_test.txt");
+        {
+            let mut trader = Trader::new().with_output_file(path.to_str().unwrap())
+                .with_market(BOSE, SyntheticMarket::new(1).with_good(USD, 0.95, 1000.0, PriceProcess::default_gbm()).build());
+            trader.wait_for(2);
+        }
+        assert!(std::fs::read_to_string(&path).unwrap().contains("1000000"));
+
+        let market = HistoricalMarket::from_visualizer_data(&path, 0).unwrap();
+        assert!(market.get_sell_price(USD, 1.0).is_ok());
+        assert!(market.get_sell_price(YEN, 1.0).is_err());
+        assert!(market.get_buy_price(YEN, 1.0).is_err());
+    }
+
+    #[test]
+    fn injected_lock_buy_error_reaches_the_trader() {
+        let faulty = FaultyMarket::wrap(BoseMarket::new_random())
+            .fail_lock_buy(Trigger::AtSteps(vec![0]), || LockBuyError::InsufficientGoodQuantityAvailable {
+                requested_good_kind: YUAN,
+                requested_good_quantity: 10.0,
+                available_good_quantity: 0.0,
+            })
+            .build();
+        let mut trader = Trader::new().with_market(BOSE, faulty.clone());
+
+        assert_eq!(trader.buy(BOSE, YUAN, 10.0), Err(TraderSupplyError::MarketInsufficientSupply));
+        assert_eq!(faulty.borrow().injections(), vec![Injection { call: FaultyCall::LockBuy, step: 0 }]);
+        assert!(trader.buy(BOSE, YUAN, 10.0).is_ok());
+    }
+
+    #[test]
+    fn injected_buy_error_leaves_no_lock_behind() {
+        let inner = SyntheticMarket::new(1).with_default_goods(1000.0).build();
+        let faulty = FaultyMarket::wrap(inner.clone())
+            .fail_buy(Trigger::AtSteps(vec![0]), || BuyError::ExpiredToken { expired_token: "lost".to_string() })
+            .build();
+        let mut trader = Trader::new().without_output_file().with_market(BOSE, faulty.clone());
+        let available = || inner.borrow().get_goods().iter().find(|g| g.good_kind == USD).map(|g| g.quantity);
+        let before = available();
+
+        assert_eq!(trader.buy(BOSE, USD, 10.0), Err(TraderSupplyError::MarketRejectedOrder));
+        assert_eq!(available(), before);
+        assert_eq!(faulty.borrow().injections(), vec![Injection { call: FaultyCall::Buy, step: 0 }]);
+
+        assert_eq!(trader.buy(BOSE, USD, 10.0), Ok(10.0));
+        assert_eq!(faulty.borrow().calls(FaultyCall::Buy), 2);
+    }
+
+    #[test]
+    fn run_stops_on_the_first_condition_met() {
+        let mut trader = Trader::new().with_market(BOSE, BoseMarket::new_random());
+        trader.set_strategy(|trader : &mut Trader| trader.wait());
+
+        let result = trader.run_until(StopCondition::Days(3).or(StopCondition::MaxIterations(10)));
+
+        assert_eq!(result.reason, StopReason::Days);
+        assert_eq!(result.iterations, 3);
+        assert_eq!(result.days, 3);
+        assert_eq!(result.capital_history.len(), 3);
+    }
+
+    #[test]
+    fn every_run_comes_to_an_end() {
+        //a strategy that replaces itself at every call still uses up its iterations
+        fn restless(trader : &mut Trader) {
+            trader.set_strategy(restless);
+        }
+        let mut trader = Trader::new();
+        trader.set_strategy(restless);
+        let result = trader.run_until(StopCondition::MaxIterations(5));
+        assert_eq!(result.reason, StopReason::MaxIterations);
+        assert_eq!(result.iterations, 5);
+
+        //nothing to wait for: nothing to run
+        let result = trader.run_until(StopCondition::Any(Vec::new()));
+        assert_eq!(result.reason, StopReason::NoCondition);
+        assert_eq!(result.iterations, 0);
+    }
+
+    #[test]
+    fn strategy_panics_are_contained() {
+        let mut trader = Trader::new().with_failure_policy(FailurePolicy::Skip);
+        trader.set_strategy(|trader : &mut Trader| {
+            trader.wait();
+            if trader.days_elapsed() == 2 {
+                panic!("bad day");
+            }
+        });
+
+        let result = trader.run_until(StopCondition::MaxIterations(3));
+
+        assert_eq!(result.reason, StopReason::MaxIterations);
+        assert_eq!(result.failures.len(), 1);
+        assert_eq!(result.failures[0].message, "bad day");
+
+        //the strategy was given back: it keeps running after the panic
+        trader.run(1);
+        assert_eq!(trader.days_elapsed(), 4);
+    }
+
+    #[test]
+    fn measured_trades_leave_out_the_orders_they_trigger() {
+        let mut trader = Trader::new()
+            .with_market(BOSE, SyntheticMarket::new(1).with_default_goods(1000.0).build())
+            .with_cost_model(FeeSchedule::new().with_fixed_fee(1.0))
+            .with_orders_after_trades();
+        trader.place_limit_buy(BOSE, YUAN, 1.0, f32::MAX);
+
+        let quoted = trader.get_supply_price_qt(BOSE, USD, 10.0).unwrap();
+        let (bought, paid) = trader.buy_measured(BOSE, USD, 10.0).unwrap();
+        assert_eq!(bought, 10.0);
+        assert!((paid - (quoted + 1.0)).abs() < 1e-4);
+        assert_eq!(trader.order_executions().len(), 1);
+
+        //both sides count the fees: a sale is worth what reaches the trader
+        let (proceeds, received) = trader.sell_measured(BOSE, USD, 10.0).unwrap();
+        assert!((proceeds - received - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn risk_breach_trips_the_kill_switch() {
+        let trader = Trader::new().with_market(BOSE, BoseMarket::new_random());
+        let limit = trader.get_owned_good_qty(YUAN) + 5.0;
+        let mut trader = trader.with_risk_limits(RiskLimits::new().with_max_position(YUAN, limit).with_kill_switch());
+
+        assert_eq!(trader.buy(BOSE, YUAN, 10.0), Err(TraderSupplyError::RiskLimitBreached(RiskViolation::MaxPosition)));
+        assert!(trader.is_kill_switch_engaged());
+        assert_eq!(trader.buy(BOSE, YUAN, 1.0), Err(TraderSupplyError::RiskLimitBreached(RiskViolation::KillSwitchEngaged)));
+        assert_eq!(trader.risk_breaches().len(), 2);
+    }
+
+    #[test]
+    fn limit_buy_executes_after_a_day() {
+        let mut trader = Trader::new().with_market(BOSE, BoseMarket::new_random());
+        let yuan = trader.get_owned_good_qty(YUAN);
+        let euros = trader.get_owned_good_qty(EUR);
+        let id = trader.place_limit_buy(BOSE, YUAN, 1.0, f32::MAX);
+        let never = trader.place_limit_buy(BOSE, YUAN, 1.0, 0.0);
+
+        trader.wait();
+
+        let executions = trader.order_executions();
+        assert_eq!(executions.len(), 1);
+        assert_eq!(executions[0].order.id, id);
+        //the value is what the purchase cost the trader
+        match executions[0].outcome {
+            OrderOutcome::Filled { value, .. } => assert!((value - (euros - trader.get_owned_good_qty(EUR))).abs() < 1e-4),
+            ref other => panic!("the limit buy didn't fill: {:?}", other),
+        }
+        assert!(trader.get_owned_good_qty(YUAN) > yuan);
+        assert_eq!(trader.open_orders().iter().map(|o| o.id).collect::<Vec<_>>(), vec![never]);
+    }
+
+    #[test]
+    fn historical_market_moves_one_row_per_day() {
+        let path = std::env::temp_dir().join("trader_history_test.csv");
+        std::fs::write(&path, "day,good,buy,sell,liquidity\n0,YUAN,0.25,0.125,500\n0,EUR,,,1000\n1,YUAN,0.75,0.5,\n").unwrap();
+
+        let mut trader = Trader::new().with_market(BOSE, HistoricalMarket::load_csv(&path).unwrap());
+        assert_eq!(trader.get_supply_price_qt(BOSE, YUAN, 10.0), Ok(2.5));
+        assert_eq!(trader.buy(BOSE, YUAN, 10.0), Ok(10.0));
+
+        trader.wait();
+        assert_eq!(trader.get_demand_price_qt(BOSE, YUAN, 10.0), 5.0);
+        assert_eq!(trader.sell(BOSE, YUAN, 10.0), Ok(5.0));
+    }
+
+    #[test]
+    fn synthetic_markets_follow_their_seed() {
+        let first = SyntheticMarket::new(7).with_default_goods(1000.0).build();
+        let second = SyntheticMarket::new(7).with_default_goods(1000.0).build();
+        let mut trader = Trader::new().with_market(BOSE, first.clone());
+        let mut other = Trader::new().with_market(BOSE, second.clone());
+
+        trader.wait_for(5);
+        other.wait_for(5);
+        assert_eq!(first.borrow().mid_price(USD), second.borrow().mid_price(USD));
+
+        //buying moves the price against the trader
+        let before = trader.get_supply_price_qt(BOSE, USD, 10.0).unwrap();
+        assert!(trader.buy(BOSE, USD, 100.0).is_ok());
+        assert!(trader.get_supply_price_qt(BOSE, USD, 10.0).unwrap() > before);
+    }
+
+    #[test]
+    fn same_seed_builds_the_same_simulation() {
+        let path = std::env::temp_dir().join("trader_seeded_visualizer_test.txt");
+        let build = || SimulationBuilder::new(42)
+            .with_market(BOSE, MarketSetup::Synthetic)
+            .with_output_file(path.to_str().unwrap())
+            .build()
+            .unwrap();
+
+        let mut first = build();
+        let mut second = build();
+        first.wait_for(3);
+        second.wait_for(3);
+        assert_eq!(first.get_supply_price_qt(BOSE, USD, 1.0), second.get_supply_price_qt(BOSE, USD, 1.0));
+
+        //the output file keeps the visualizer's format and can be replayed; the seed goes next to it
+        drop(first);
+        assert!(std::fs::read_to_string(&path).unwrap().starts_with('['));
+        assert!(std::fs::read_to_string(seed_file(path.to_str().unwrap())).unwrap().contains("\"seed\":42"));
+        assert!(HistoricalMarket::from_visualizer_data(&path, 0).unwrap().days() > 1);
+    }
+
+    #[test]
+    fn monte_carlo_reports_every_seed() {
+        let world = |seed : u64| SimulationBuilder::new(seed).with_market(BOSE, MarketSetup::Synthetic);
+        let strategy = |trader : &mut Trader| {
+            let _ = trader.buy(BOSE, USD, 100.0);
+            trader.wait();
+        };
+
+        let report = MonteCarlo::new(world, strategy)
+            .with_runs(1, 8)
+            .with_stop_condition(StopCondition::Days(5))
+            .run();
+
+        assert_eq!(report.runs.len(), 8);
+        assert_eq!(report.failed_runs, 0);
+        assert!(report.runs.iter().all(|r| r.result.as_ref().unwrap().days == 5));
+        let capital = report.final_capital.unwrap();
+        assert_eq!(capital.count, 8);
+        assert!(capital.min <= capital.median && capital.median <= capital.max);
+
+        let path = std::env::temp_dir().join("trader_monte_carlo_test.csv");
+        report.write_csv(&path).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        assert!(csv.starts_with("seed,"));
+        assert!(csv.lines().nth(1).unwrap().starts_with(&format!("{},", report.runs[0].seed)));
+    }
+
+    #[test]
+    fn a_world_that_panics_fails_only_its_own_run() {
+        let world = |seed : u64| {
+            if seed == 13 {
+                panic!("no such world");
+            }
+            SimulationBuilder::new(seed).with_market(BOSE, MarketSetup::Synthetic)
+        };
+        let report = MonteCarlo::new(world, |trader : &mut Trader| trader.wait())
+            .with_seeds(vec![12, 13, 14])
+            .with_stop_condition(StopCondition::Days(2))
+            .run();
+
+        assert_eq!(report.failed_runs, 1);
+        assert!(report.runs[1].result.as_ref().unwrap_err().contains("no such world"));
+        assert!(report.runs[0].result.is_ok() && report.runs[2].result.is_ok());
+    }
+
+    #[test]
+    fn monte_carlo_runs_a_month_by_default() {
+        let report = MonteCarlo::new(|seed : u64| SimulationBuilder::new(seed).with_market(BOSE, MarketSetup::Synthetic), |trader : &mut Trader| trader.wait())
+            .with_runs(2, 2)
+            .run();
+        assert!(report.runs.iter().all(|r| r.result.as_ref().unwrap().days == 30));
+
+        //a strategy that never waits still comes to an end
+        let report = MonteCarlo::new(|seed : u64| SimulationBuilder::new(seed).with_market(BOSE, MarketSetup::Synthetic), |_ : &mut Trader| {})
+            .with_runs(2, 1)
+            .run();
+        assert_eq!(report.runs[0].result.as_ref().unwrap().days, 0);
+    }
+
+    #[test]
+    fn tournament_ranks_strategies_on_the_same_worlds() {
+        let report = Tournament::new(|seed : u64| SimulationBuilder::new(seed).with_market(BOSE, MarketSetup::Synthetic))
+            .with_strategy("holder", |trader : &mut Trader| trader.wait())
+            .with_strategy("buyer", |trader : &mut Trader| {
+                let _ = trader.buy(BOSE, USD, 100.0);
+                trader.wait();
+            })
+            .with_runs(3, 6)
+            .with_stop_condition(StopCondition::Days(3))
+            .run();
+
+        assert_eq!(report.matches.len(), 12);
+        assert_eq!(report.leaderboard.iter().map(|s| s.rank).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(report.comparisons.len(), 1);
+        assert_eq!(report.comparisons[0].samples, 6);
+
+        let path = std::env::temp_dir().join("trader_tournament_test.csv");
+        report.write_csv(&path).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(csv.lines().count(), 13);
+        assert!(report.seeds.iter().all(|seed| csv.contains(&format!("\n{},", seed))));
+
+        //t = 4.24 with 4 degrees of freedom
+        let p = paired_t_test("a", "b", &[1.0, 2.0, 3.0, 4.0, 5.0]).p_value;
+        assert!(p > 0.012 && p < 0.015);
+    }
+
+    #[test]
+    fn tournament_pairs_the_matches_of_a_repeated_seed_by_position() {
+        //the flaky strategy panics in the first world it plays, whichever that is
+        let panicked = Arc::new(AtomicBool::new(false));
+        let report = Tournament::new(|seed : u64| SimulationBuilder::new(seed).with_market(BOSE, MarketSetup::Synthetic))
+            .with_strategy("holder", |trader : &mut Trader| trader.wait())
+            .with_strategy("flaky", move |trader : &mut Trader| {
+                if !panicked.swap(true, Ordering::SeqCst) {
+                    panic!("first world");
+                }
+                trader.wait();
+            })
+            .with_seeds(vec![5, 5])
+            .with_stop_condition(StopCondition::Days(2))
+            .run();
+
+        let flaky = report.leaderboard.iter().find(|s| s.strategy == "flaky").unwrap();
+        assert_eq!(flaky.failed_runs, 1);
+        assert_eq!(report.comparisons[0].samples, 1);
+    }
+
+    #[test]
+    fn optimizer_ranks_every_grid_point() {
+        let factory = |params : &Params| -> Box<dyn Fn(&mut Trader)> {
+            let amount = params["amount"];
+            Box::new(move |trader : &mut Trader| {
+                if amount > 0.0 {
+                    let _ = trader.buy(BOSE, USD, amount);
+                }
+                trader.wait();
+            })
+        };
+        let report = Optimizer::new(|seed : u64| SimulationBuilder::new(seed).with_market(BOSE, MarketSetup::Synthetic), factory,
+                                    ParameterSpace::new().with_values("amount", vec![0.0, 50.0, 100.0]))
+            .with_objective(Objective::DrawdownAdjustedReturn)
+            .with_split(5, 3, 2)
+            .with_stop_condition(StopCondition::Days(3))
+            .run();
+
+        assert_eq!(report.evaluations.iter().map(|e| e.rank).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(report.evaluations.iter().all(|e| e.test_score.is_some() && e.failed_runs == 0));
+
+        let path = std::env::temp_dir().join("trader_optimizer_test.csv");
+        report.write_csv(&path).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        assert!(csv.starts_with("rank,amount,train_score,test_score"));
+        assert!(csv.lines().next().unwrap().ends_with("train_seeds,test_seeds"));
+        assert_eq!(csv.lines().count(), 4);
+        assert_eq!(report.train_seeds.len() + report.test_seeds.len(), 5);
+    }
+
+    #[test]
+    fn failed_runs_score_below_every_completed_one() {
+        let run = |capital_history : Vec<f32>| -> Result<RunResult, String> { Ok(RunResult {
+            reason: StopReason::Days,
+            iterations: capital_history.len() as u32,
+            days: capital_history.len() as u32,
+            starting_capital: 100.0,
+            final_capital: *capital_history.last().unwrap(),
+            capital_history,
+            final_goods: Default::default(),
+            errors: Vec::new(),
+            failures: Vec::new(),
+        }) };
+        let losing = run(vec![90.0, 85.0, 75.0]);
+        let failed: Result<RunResult, String> = Err("the world couldn't be built".to_string());
+
+        let alone = Objective::Sharpe.score(&[losing.clone()]);
+        assert!(Objective::Sharpe.score(&[losing.clone(), failed.clone()]) < alone);
+        assert_eq!(Objective::Sharpe.score(&[failed.clone()]), -1.0);
+        assert_eq!(Objective::FinalCapital.score(&[losing, failed]), 37.5);
+    }
+
+    #[test]
+    fn environment_steps_one_day_per_action() {
+        let mut env = TradingEnvironment::new(|seed : u64| SimulationBuilder::new(seed).with_market(BOSE, MarketSetup::Synthetic), vec![BOSE])
+            .with_max_days(3);
+        let observation = env.reset(1).unwrap();
+        assert_eq!(observation.len(), env.observation_labels().len());
+        assert_eq!(env.action_space().discrete_len(), 7);
+
+        let buy = env.action_space().action(1).unwrap();
+        let step = env.step(buy);
+        assert!(step.errors.is_empty() && !step.done);
+        assert!(env.trader().unwrap().get_owned_good_qty(USD) > 0.0);
+
+        env.step(Action::Hold);
+        assert!(env.step(Action::Continuous(vec![-1.0, 0.0, 0.0])).done);
+
+        let path = std::env::temp_dir().join("trader_trajectory_test.jsonl");
+        env.export_trajectory(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "call reset() first")]
+    fn environment_refuses_to_step_past_the_end() {
+        let mut env = TradingEnvironment::new(|seed : u64| SimulationBuilder::new(seed).with_market(BOSE, MarketSetup::Synthetic), vec![BOSE])
+            .with_max_days(1);
+        env.reset(1).unwrap();
+        assert!(env.step(Action::Hold).done);
+        env.step(Action::Hold);
+    }
+
+    #[test]
+    fn conversion_goes_through_euros() {
+        let mut trader = Trader::new()
+            .with_market(BOSE, SyntheticMarket::new(1).with_default_goods(1000.0).build())
+            .with_market(BFB, SyntheticMarket::new(2).with_default_goods(1000.0).build())
+            .with_good(YEN, 10000.0);
+
+        let plan = trader.plan_conversion(YEN, USD, 10000.0).unwrap();
+        assert_eq!(plan.hops.iter().map(|h| h.side).collect::<Vec<_>>(), vec![TradeSide::Sell, TradeSide::Buy]);
+
+        let report = trader.convert(YEN, USD, 10000.0).unwrap();
+        assert!(report.is_complete());
+        assert!(report.output > 0.0);
+        assert_eq!(trader.get_owned_good_qty(YEN), 0.0);
+        assert_eq!(trader.get_owned_good_qty(USD), report.output);
+        //the buy only spends what the sell raised, not the euros the trader already had
+        assert_eq!(report.hops[1].quantity_in, *report.hops[0].result.as_ref().unwrap());
+        assert!(trader.get_owned_good_qty(EUR) > 999.99);
+    }
+
+    #[test]
+    fn rebalance_buys_the_missing_weights() {
+        let mut trader = Trader::new()
+            .with_market(BOSE, SyntheticMarket::new(1).with_default_goods(100000.0).build())
+            .with_market(BFB, SyntheticMarket::new(2).with_default_goods(100000.0).build());
+        let policy = RebalancePolicy::new(&[(USD, 0.2), (YEN, 0.2), (YUAN, 0.2)], 0.05);
+
+        let report = trader.rebalance(&policy);
+        assert_eq!(report.trades.len(), 3);
+        assert!(report.trades.iter().all(|t| t.side == TradeSide::Buy && matches!(t.outcome, OrderOutcome::Filled { .. })));
+        for kind in [USD, YEN, YUAN] {
+            assert!((report.after.weight(kind) - 0.2).abs() < 0.05);
+        }
+
+        //already in the band: nothing to do
+        assert!(trader.rebalance(&policy).trades.is_empty());
+    }
+
+    #[test]
+    fn rebalance_sells_what_it_can_when_no_market_can_pay_it_all() {
+        //the market can pay for the USD or for the YEN, but not for both
+        let mut trader = Trader::new()
+            .with_market(BOSE, SyntheticMarket::new(1).with_default_goods(1000.0).with_budget(1000.0).build())
+            .with_good(USD, 900.0)
+            .with_good(YEN, 100000.0);
+        let euros = trader.get_owned_good_qty(EUR);
+
+        let report = trader.rebalance(&RebalancePolicy::new(&[(EUR, 1.0)], 0.05));
+        assert_eq!(report.trades.len(), 2);
+        let yen = &report.trades[1];
+        assert_eq!(yen.kind, YEN);
+        match yen.outcome {
+            OrderOutcome::Filled { quantity, value } => {
+                assert!(value > 0.0 && value < yen.planned_eur);
+                assert!((trader.get_owned_good_qty(YEN) - (100000.0 - quantity)).abs() < 1.0);
+            }
+            ref other => panic!("nothing was sold: {:?}", other),
+        }
+
+        //the fills are what the trades really did
+        let received: f32 = report.trades.iter().map(|t| match t.outcome { OrderOutcome::Filled { value, .. } => value, _ => 0.0 }).sum();
+        assert!((trader.get_owned_good_qty(EUR) - euros - received).abs() < 1e-2);
+    }
+
+    #[test]
+    fn twap_trades_one_slice_per_day() {
+        let mut trader = Trader::new()
+            .with_market(BOSE, SyntheticMarket::new(1).with_default_goods(1000.0).build());
+        let id = trader.execute_twap(BOSE, YUAN, TradeSide::Buy, 400.0, 4);
+
+        trader.wait();
+        assert_eq!(trader.execution(id).map(|o| o.slices.len()), Some(1));
+        assert!((trader.get_owned_good_qty(YUAN) - 100.0).abs() < 0.01);
+
+        trader.wait_for(3);
+        let order: &ParentOrder = trader.execution(id).unwrap();
+        assert!(trader.active_executions().is_empty());
+        assert!(order.is_complete());
+        assert_eq!(order.slices.len(), 4);
+        assert!(order.average_price().is_some() && order.slippage().is_some());
+        //the slices are valued by the euros that really left the trader
+        assert!((order.value() - (1000.0 - trader.get_owned_good_qty(EUR))).abs() < 1e-3);
+    }
+
+    #[test]
+    fn vwap_skips_the_days_the_market_takes_nothing() {
+        let mut trader = Trader::new()
+            .with_market(BOSE, SyntheticMarket::new(1).with_default_goods(1000.0).build())
+            .with_good(USD, 100.0);
+        let id = trader.execute_vwap(BOSE, USD, TradeSide::Sell, 50.0, 2, 0.0);
+
+        trader.wait_for(2);
+        let order = trader.execution(id).unwrap();
+        assert!(order.slices.iter().all(|s| s.outcome == OrderOutcome::Skipped));
+        assert_eq!(order.filled(), 0.0);
+        assert_eq!(trader.get_owned_good_qty(USD), 100.0);
+    }
+
+    #[test]
+    fn single_attempt_negotiation_locks_at_the_quote() {
+        let mut trader = Trader::new()
+            .with_market(BOSE, SyntheticMarket::new(1).with_default_goods(1000.0).build())
+            .with_negotiation(Negotiation::new(0.05, 1));
+
+        trader.buy(BOSE, USD, 100.0).unwrap();
+        trader.sell(BOSE, USD, 50.0).unwrap();
+        let records = trader.negotiation_records();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|r| r.attempts == 1 && r.savings() == 0.0));
+    }
+
+    #[test]
+    fn negotiation_finds_the_market_limit() {
+        let mut trader = Trader::new()
+            .with_market(BOSE, SyntheticMarket::new(1).with_default_goods(1000.0).with_haggle_room(0.02).build())
+            .with_negotiation(Negotiation::new(0.05, 4));
+
+        trader.buy(BOSE, USD, 100.0).unwrap();
+        trader.sell(BOSE, USD, 50.0).unwrap();
+
+        let records = trader.negotiation_records();
+        assert_eq!(records.len(), 2);
+        //the first price is refused, the market's own limit is taken at the second attempt
+        assert!(records.iter().all(|r| r.attempts == 2 && r.savings() > 0.0));
+        assert!((records[0].agreed - records[0].quoted * 0.98).abs() < 0.01);
+        assert!(trader.negotiation_savings() > 0.0);
+    }
 }
@@ -0,0 +1,8 @@
+pub mod market_codec;
+pub mod recording_market;
+pub mod replay_market;
+pub mod faulty_market;
+mod lock_book;
+pub mod historical_market;
+pub mod synthetic_market;
+pub mod threaded_market;
@@ -0,0 +1,268 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use bose::market::BoseMarket;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use market_common::event::event::Event;
+use market_common::event::notifiable::Notifiable;
+use market_common::good::good::Good;
+use market_common::good::good_kind::GoodKind;
+use market_common::market::good_label::GoodLabel;
+use market_common::market::{BuyError, LockBuyError, LockSellError, Market, MarketGetterError, SellError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FaultyCall {
+    GetBuyPrice,
+    GetSellPrice,
+    LockBuy,
+    Buy,
+    LockSell,
+    Sell,
+}
+
+//When a fault fires. Steps are counted per call kind, starting from 0: AtSteps(vec![2]) fails the third lock_buy.
+//The buy and sell faults are decided when the lock is made, so the n-th buy step is the purchase of the n-th lock_buy
+//that got past the lock faults (and the same for sell).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Trigger {
+    Always,
+    AtSteps(Vec<u64>),
+    //fails each call with this probability, drawn from the market's seeded generator
+    Rate(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Injection {
+    pub call: FaultyCall,
+    pub step: u64,
+}
+
+struct Fault<E> {
+    call: FaultyCall,
+    trigger: Trigger,
+    //the market errors don't have to be Clone, so we build a fresh one every time
+    error: Box<dyn Fn() -> E>,
+}
+
+//the error a token will get instead of its goods
+enum DoomedLock {
+    Buy(BuyError),
+    Sell(SellError),
+}
+
+//Wraps a market and makes chosen calls fail with chosen errors instead of reaching the inner market.
+//Useful to exercise the trader's (and the strategies') error paths on purpose.
+//A lock whose buy or sell is going to fail never reaches the inner market: it gets a token of the FaultyMarket,
+//so no lock is left open in there when the fault fires.
+pub struct FaultyMarket {
+    inner: Rc<RefCell<dyn Market>>,
+    rng: RefCell<StdRng>,
+    //the steps the triggers look at (see Trigger)
+    steps: RefCell<HashMap<FaultyCall, u64>>,
+    calls: RefCell<HashMap<FaultyCall, u64>>,
+    injections: RefCell<Vec<Injection>>,
+    doomed_locks: HashMap<String, DoomedLock>,
+    next_doomed_token: u64,
+    getter_faults: Vec<Fault<MarketGetterError>>,
+    lock_buy_faults: Vec<Fault<LockBuyError>>,
+    buy_faults: Vec<Fault<BuyError>>,
+    lock_sell_faults: Vec<Fault<LockSellError>>,
+    sell_faults: Vec<Fault<SellError>>,
+}
+
+impl FaultyMarket {
+
+    pub fn wrap(inner : Rc<RefCell<dyn Market>>) -> Self {
+        FaultyMarket {
+            inner,
+            rng: RefCell::new(StdRng::seed_from_u64(0)),
+            steps: RefCell::new(HashMap::new()),
+            calls: RefCell::new(HashMap::new()),
+            injections: RefCell::new(Vec::new()),
+            doomed_locks: HashMap::new(),
+            next_doomed_token: 0,
+            getter_faults: Vec::new(),
+            lock_buy_faults: Vec::new(),
+            buy_faults: Vec::new(),
+            lock_sell_faults: Vec::new(),
+            sell_faults: Vec::new(),
+        }
+    }
+
+    pub fn with_seed(self, seed : u64) -> Self {
+        *self.rng.borrow_mut() = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn fail_get_buy_price(mut self, trigger : Trigger, error : impl Fn() -> MarketGetterError + 'static) -> Self {
+        self.getter_faults.push(Fault { call: FaultyCall::GetBuyPrice, trigger, error: Box::new(error) });
+        self
+    }
+
+    pub fn fail_get_sell_price(mut self, trigger : Trigger, error : impl Fn() -> MarketGetterError + 'static) -> Self {
+        self.getter_faults.push(Fault { call: FaultyCall::GetSellPrice, trigger, error: Box::new(error) });
+        self
+    }
+
+    pub fn fail_lock_buy(mut self, trigger : Trigger, error : impl Fn() -> LockBuyError + 'static) -> Self {
+        self.lock_buy_faults.push(Fault { call: FaultyCall::LockBuy, trigger, error: Box::new(error) });
+        self
+    }
+
+    pub fn fail_buy(mut self, trigger : Trigger, error : impl Fn() -> BuyError + 'static) -> Self {
+        self.buy_faults.push(Fault { call: FaultyCall::Buy, trigger, error: Box::new(error) });
+        self
+    }
+
+    pub fn fail_lock_sell(mut self, trigger : Trigger, error : impl Fn() -> LockSellError + 'static) -> Self {
+        self.lock_sell_faults.push(Fault { call: FaultyCall::LockSell, trigger, error: Box::new(error) });
+        self
+    }
+
+    pub fn fail_sell(mut self, trigger : Trigger, error : impl Fn() -> SellError + 'static) -> Self {
+        self.sell_faults.push(Fault { call: FaultyCall::Sell, trigger, error: Box::new(error) });
+        self
+    }
+
+    //keep a clone of the returned Rc to look at the injections later: it coerces to Rc<RefCell<dyn Market>>
+    pub fn build(self) -> Rc<RefCell<FaultyMarket>> {
+        Rc::new(RefCell::new(self))
+    }
+
+    //every fault that fired so far, in order
+    pub fn injections(&self) -> Vec<Injection> {
+        self.injections.borrow().clone()
+    }
+
+    //how many times the market was asked this, faults included
+    pub fn calls(&self, call : FaultyCall) -> u64 {
+        *self.calls.borrow().get(&call).unwrap_or(&0)
+    }
+
+    fn count(&self, call : FaultyCall) {
+        *self.calls.borrow_mut().entry(call).or_insert(0) += 1;
+    }
+
+    fn doomed_token(&mut self, lock : DoomedLock) -> String {
+        let token = format!("FAULTY-{}", self.next_doomed_token);
+        self.next_doomed_token += 1;
+        self.doomed_locks.insert(token.clone(), lock);
+        token
+    }
+
+    //moves the step on and returns the error to inject, if any
+    fn check<E>(&self, call : FaultyCall, faults : &[Fault<E>]) -> Option<E> {
+        let step = {
+            let mut steps = self.steps.borrow_mut();
+            let counter = steps.entry(call).or_insert(0);
+            let step = *counter;
+            *counter += 1;
+            step
+        };
+
+        let fault = faults.iter().filter(|f| f.call == call).find(|f| match &f.trigger {
+            Trigger::Always => true,
+            Trigger::AtSteps(steps) => steps.contains(&step),
+            Trigger::Rate(rate) => self.rng.borrow_mut().gen::<f64>() < *rate,
+        })?;
+
+        self.injections.borrow_mut().push(Injection { call, step });
+        Some((fault.error)())
+    }
+}
+
+impl Notifiable for FaultyMarket {
+    fn add_subscriber(&mut self, subscriber : Box<dyn Notifiable>) {
+        self.inner.borrow_mut().add_subscriber(subscriber);
+    }
+
+    fn on_event(&mut self, event : Event) {
+        self.inner.borrow_mut().on_event(event);
+    }
+}
+
+//The constructors wrap a BoseMarket without any fault. Use wrap() to configure them.
+impl Market for FaultyMarket {
+    fn new_random() -> Rc<RefCell<dyn Market>> where Self: Sized {
+        FaultyMarket::wrap(BoseMarket::new_random()).build()
+    }
+
+    fn new_with_quantities(eur : f32, yen : f32, usd : f32, yuan : f32) -> Rc<RefCell<dyn Market>> where Self: Sized {
+        FaultyMarket::wrap(BoseMarket::new_with_quantities(eur, yen, usd, yuan)).build()
+    }
+
+    fn new_file(path : &str) -> Rc<RefCell<dyn Market>> where Self: Sized {
+        FaultyMarket::wrap(BoseMarket::new_file(path)).build()
+    }
+
+    fn get_name(&self) -> &'static str {
+        self.inner.borrow().get_name()
+    }
+
+    fn get_budget(&self) -> f32 {
+        self.inner.borrow().get_budget()
+    }
+
+    fn get_buy_price(&self, kind : GoodKind, quantity : f32) -> Result<f32, MarketGetterError> {
+        self.count(FaultyCall::GetBuyPrice);
+        if let Some(e) = self.check(FaultyCall::GetBuyPrice, &self.getter_faults) {
+            return Err(e);
+        }
+        self.inner.borrow().get_buy_price(kind, quantity)
+    }
+
+    fn get_sell_price(&self, kind : GoodKind, quantity : f32) -> Result<f32, MarketGetterError> {
+        self.count(FaultyCall::GetSellPrice);
+        if let Some(e) = self.check(FaultyCall::GetSellPrice, &self.getter_faults) {
+            return Err(e);
+        }
+        self.inner.borrow().get_sell_price(kind, quantity)
+    }
+
+    fn get_goods(&self) -> Vec<GoodLabel> {
+        self.inner.borrow().get_goods()
+    }
+
+    fn lock_buy(&mut self, kind_to_buy : GoodKind, quantity_to_buy : f32, bid : f32, trader_name : String) -> Result<String, LockBuyError> {
+        self.count(FaultyCall::LockBuy);
+        if let Some(e) = self.check(FaultyCall::LockBuy, &self.lock_buy_faults) {
+            return Err(e);
+        }
+        if let Some(e) = self.check(FaultyCall::Buy, &self.buy_faults) {
+            return Ok(self.doomed_token(DoomedLock::Buy(e)));
+        }
+        self.inner.borrow_mut().lock_buy(kind_to_buy, quantity_to_buy, bid, trader_name)
+    }
+
+    fn buy(&mut self, token : String, cash : &mut Good) -> Result<Good, BuyError> {
+        self.count(FaultyCall::Buy);
+        match self.doomed_locks.remove(&token) {
+            Some(DoomedLock::Buy(e)) => Err(e),
+            Some(DoomedLock::Sell(_)) => Err(BuyError::UnrecognizedToken { unrecognized_token: token }),
+            None => self.inner.borrow_mut().buy(token, cash),
+        }
+    }
+
+    fn lock_sell(&mut self, kind_to_sell : GoodKind, quantity_to_sell : f32, offer : f32, trader_name : String) -> Result<String, LockSellError> {
+        self.count(FaultyCall::LockSell);
+        if let Some(e) = self.check(FaultyCall::LockSell, &self.lock_sell_faults) {
+            return Err(e);
+        }
+        if let Some(e) = self.check(FaultyCall::Sell, &self.sell_faults) {
+            return Ok(self.doomed_token(DoomedLock::Sell(e)));
+        }
+        self.inner.borrow_mut().lock_sell(kind_to_sell, quantity_to_sell, offer, trader_name)
+    }
+
+    fn sell(&mut self, token : String, good : &mut Good) -> Result<Good, SellError> {
+        self.count(FaultyCall::Sell);
+        match self.doomed_locks.remove(&token) {
+            Some(DoomedLock::Sell(e)) => Err(e),
+            Some(DoomedLock::Buy(_)) => Err(SellError::UnrecognizedToken { unrecognized_token: token }),
+            None => self.inner.borrow_mut().sell(token, good),
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use serde_json::Value;
+
+use market_common::event::event::{Event, EventKind};
+use market_common::event::notifiable::Notifiable;
+use market_common::good::good::Good;
+use market_common::good::good_kind::GoodKind;
+use market_common::good::good_kind::GoodKind::*;
+use market_common::market::good_label::GoodLabel;
+use market_common::market::{BuyError, LockBuyError, LockSellError, Market, MarketGetterError, SellError};
+
+use crate::markets::lock_book::LockBook;
+use crate::markets::market_codec as codec;
+use crate::trader::INFINITY;
+
+//used when the data doesn't say how much of something the market has
+static DEFAULT_LIQUIDITY : f32 = 100_000.0;
+
+//One day of data. Prices are in EUR per unit: `buy` is what the trader pays, `sell` what the trader gets.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PriceRow {
+    pub buy: HashMap<GoodKind, f32>,
+    pub sell: HashMap<GoodKind, f32>,
+    //when given, the market's inventory of the good is reset to it at the start of the day
+    pub liquidity: HashMap<GoodKind, f32>,
+}
+
+//A market that plays back a price history, one row per day (every wait() moves to the next row).
+//Once the data runs out the market stays on the last row: check is_exhausted() to stop a backtest in time.
+//
+//The data can come from:
+// - a CSV file with the columns `day,good,buy,sell[,liquidity]`, one line per day and good. Empty cells are allowed.
+// - the visualizer file written by the Trader, in which case every sample of the chosen market is a row.
+//Missing prices are carried over from the previous row; a good with no price at all can't be traded.
+pub struct HistoricalMarket {
+    name: &'static str,
+    rows: Vec<PriceRow>,
+    cursor: usize,
+    book: LockBook,
+}
+
+fn invalid_data(msg : String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+//missing prices are taken from the day before
+fn fill_forward(rows : &mut [PriceRow]) {
+    for i in 1..rows.len() {
+        let (before, after) = rows.split_at_mut(i);
+        let previous = &before[i - 1];
+        let row = &mut after[0];
+        for (kind, price) in previous.buy.iter() {
+            row.buy.entry(*kind).or_insert(*price);
+        }
+        for (kind, price) in previous.sell.iter() {
+            row.sell.entry(*kind).or_insert(*price);
+        }
+    }
+}
+
+fn parse_cell(cell : Option<&&str>, line : usize) -> io::Result<Option<f32>> {
+    match cell.map(|c| c.trim()) {
+        None | Some("") => Ok(None),
+        Some(c) => c.parse::<f32>().map(Some).map_err(|_| invalid_data(format!("line {}: \"{}\" is not a number", line, c))),
+    }
+}
+
+//a price that is not a finite positive number (the visualizer writes null for "no price") counts as missing,
+//and so does the trader's INFINITY placeholder, which save_data writes when a market couldn't quote
+fn valid_price(price : Option<f32>) -> Option<f32> {
+    price.filter(|p| p.is_finite() && *p > 0.0 && *p < INFINITY)
+}
+
+impl HistoricalMarket {
+
+    pub fn from_rows(name : &'static str, rows : Vec<PriceRow>) -> Self {
+        let mut rows = rows;
+        if rows.is_empty() {
+            rows.push(PriceRow::default());
+        }
+        fill_forward(&mut rows);
+
+        let inventory = [EUR, USD, YEN, YUAN].iter()
+            .map(|kind| (*kind, rows[0].liquidity.get(kind).copied().unwrap_or(DEFAULT_LIQUIDITY)))
+            .collect();
+        HistoricalMarket { name, rows, cursor: 0, book: LockBook::new("HIST", inventory) }
+    }
+
+    pub fn load_csv(path : impl AsRef<Path>) -> io::Result<Rc<RefCell<dyn Market>>> {
+        Ok(Rc::new(RefCell::new(Self::from_csv(path)?)))
+    }
+
+    pub fn from_csv(path : impl AsRef<Path>) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let mut lines = content.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+
+        let (_, header) = lines.next().ok_or_else(|| invalid_data("empty CSV file".to_string()))?;
+        let columns: Vec<String> = header.split(',').map(|c| c.trim().to_lowercase()).collect();
+        let column = |name : &str| columns.iter().position(|c| c == name);
+        let (day_col, good_col) = match (column("day"), column("good")) {
+            (Some(d), Some(g)) => (d, g),
+            _ => return Err(invalid_data("the CSV header needs at least the \"day\" and \"good\" columns".to_string())),
+        };
+        let (buy_col, sell_col, liquidity_col) = (column("buy"), column("sell"), column("liquidity"));
+
+        let mut days: BTreeMap<u32, PriceRow> = BTreeMap::new();
+        for (index, line) in lines {
+            let line_number = index + 1;
+            let cells: Vec<&str> = line.split(',').collect();
+
+            let day = cells.get(day_col).and_then(|d| d.trim().parse::<u32>().ok())
+                .ok_or_else(|| invalid_data(format!("line {}: invalid day", line_number)))?;
+            let kind = cells.get(good_col).and_then(|g| codec::parse_good_kind(&Value::String(g.trim().to_uppercase())))
+                .ok_or_else(|| invalid_data(format!("line {}: invalid good", line_number)))?;
+
+            let row = days.entry(day).or_default();
+            if let Some(price) = valid_price(buy_col.map_or(Ok(None), |c| parse_cell(cells.get(c), line_number))?) {
+                row.buy.insert(kind, price);
+            }
+            if let Some(price) = valid_price(sell_col.map_or(Ok(None), |c| parse_cell(cells.get(c), line_number))?) {
+                row.sell.insert(kind, price);
+            }
+            if let Some(quantity) = liquidity_col.map_or(Ok(None), |c| parse_cell(cells.get(c), line_number))? {
+                row.liquidity.insert(kind, quantity);
+            }
+        }
+
+        Ok(Self::from_rows("HISTORICAL", days.into_values().collect()))
+    }
+
+    pub fn load_visualizer_data(path : impl AsRef<Path>, market_index : usize) -> io::Result<Rc<RefCell<dyn Market>>> {
+        Ok(Rc::new(RefCell::new(Self::from_visualizer_data(path, market_index)?)))
+    }
+
+    //`market_index` is the position of the market in the file: the markets are in the order the trader attached them
+    //(Trader::data_index() tells where each one is).
+    //The trader writes the plain array (a seeded one puts its seed in trader::seed_file()); the files wrapped with
+    //the seed ({"seed": ..., "data": [...]}) that seeded simulations used to write are read too.
+    pub fn from_visualizer_data(path : impl AsRef<Path>, market_index : usize) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let value: Value = serde_json::from_str(&content).map_err(|e| invalid_data(e.to_string()))?;
+        let data = match value.get("data") {
+            Some(data) => data,
+            None => &value,
+        };
+
+        let market = data.as_array().and_then(|markets| markets.get(market_index))
+            .ok_or_else(|| invalid_data(format!("the file has no market #{}", market_index)))?;
+        //[buy prices, sell prices, liquidity], each one a map from good to samples
+        let series = |i : usize| -> Vec<(GoodKind, Vec<Option<f32>>)> {
+            market.get(i).and_then(|s| s.as_object()).map_or(Vec::new(), |map| map.iter()
+                .filter_map(|(kind, samples)| {
+                    let kind = codec::parse_good_kind(&Value::String(kind.clone()))?;
+                    let samples = samples.as_array()?.iter().map(codec::parse_f32).collect();
+                    Some((kind, samples))
+                })
+                .collect())
+        };
+        let (buy, sell, liquidity) = (series(0), series(1), series(2));
+
+        let len = buy.iter().chain(sell.iter()).chain(liquidity.iter()).map(|(_, s)| s.len()).max().unwrap_or(0);
+        let mut rows = vec![PriceRow::default(); len];
+        for (kind, samples) in buy.iter() {
+            for (row, price) in rows.iter_mut().zip(samples.iter()) {
+                if let Some(price) = valid_price(*price) {
+                    row.buy.insert(*kind, price);
+                }
+            }
+        }
+        for (kind, samples) in sell.iter() {
+            for (row, price) in rows.iter_mut().zip(samples.iter()) {
+                if let Some(price) = valid_price(*price) {
+                    row.sell.insert(*kind, price);
+                }
+            }
+        }
+        for (kind, samples) in liquidity.iter() {
+            for (row, quantity) in rows.iter_mut().zip(samples.iter()) {
+                if let Some(quantity) = quantity {
+                    row.liquidity.insert(*kind, *quantity);
+                }
+            }
+        }
+
+        Ok(Self::from_rows("HISTORICAL", rows))
+    }
+
+    pub fn with_name(mut self, name : &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    //the index of the row in use
+    pub fn day(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn days(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.cursor + 1 >= self.rows.len()
+    }
+
+    fn row(&self) -> &PriceRow {
+        &self.rows[self.cursor]
+    }
+
+    fn unit_buy_price(&self, kind : GoodKind) -> Option<f32> {
+        if kind == EUR { Some(1.0) } else { self.row().buy.get(&kind).copied() }
+    }
+
+    fn unit_sell_price(&self, kind : GoodKind) -> Option<f32> {
+        if kind == EUR { Some(1.0) } else { self.row().sell.get(&kind).copied() }
+    }
+
+    fn not_tradable(kind : GoodKind, quantity : f32) -> MarketGetterError {
+        MarketGetterError::InsufficientGoodQuantityAvailable { requested_good_kind: kind, requested_good_quantity: quantity, available_good_quantity: 0.0 }
+    }
+
+    fn next_day(&mut self) {
+        self.book.advance_day();
+        if self.cursor + 1 < self.rows.len() {
+            self.cursor += 1;
+        }
+        let liquidity: Vec<(GoodKind, f32)> = self.row().liquidity.iter().map(|(k, q)| (*k, *q)).collect();
+        for (kind, quantity) in liquidity {
+            self.book.set_quantity(kind, quantity);
+        }
+    }
+}
+
+impl Notifiable for HistoricalMarket {
+    fn add_subscriber(&mut self, subscriber : Box<dyn Notifiable>) {
+        self.book.add_subscriber(subscriber);
+    }
+
+    //the other markets' trades don't change the history: only the days matter
+    fn on_event(&mut self, event : Event) {
+        if let EventKind::Wait = event.kind {
+            self.next_day();
+        }
+    }
+}
+
+impl Market for HistoricalMarket {
+    fn new_random() -> Rc<RefCell<dyn Market>> where Self: Sized {
+        panic!("A HistoricalMarket needs a price history: use HistoricalMarket::new_file()");
+    }
+
+    fn new_with_quantities(_ : f32, _ : f32, _ : f32, _ : f32) -> Rc<RefCell<dyn Market>> where Self: Sized {
+        panic!("A HistoricalMarket needs a price history: use HistoricalMarket::new_file()");
+    }
+
+    //a .csv file, or the first market of a visualizer file
+    fn new_file(path : &str) -> Rc<RefCell<dyn Market>> where Self: Sized {
+        let market = if path.to_lowercase().ends_with(".csv") {
+            Self::load_csv(path)
+        } else {
+            Self::load_visualizer_data(path, 0)
+        };
+        market.unwrap_or_else(|e| panic!("Couldn't load the price history \"{}\": {}", path, e))
+    }
+
+    fn get_name(&self) -> &'static str {
+        self.name
+    }
+
+    fn get_budget(&self) -> f32 {
+        self.book.budget()
+    }
+
+    fn get_buy_price(&self, kind : GoodKind, quantity : f32) -> Result<f32, MarketGetterError> {
+        self.book.check_quote(kind, quantity)?;
+        self.unit_buy_price(kind).map(|p| p * quantity).ok_or_else(|| Self::not_tradable(kind, quantity))
+    }
+
+    fn get_sell_price(&self, kind : GoodKind, quantity : f32) -> Result<f32, MarketGetterError> {
+        if quantity <= 0.0 {
+            return Err(MarketGetterError::NonPositiveQuantityAsked);
+        }
+        self.unit_sell_price(kind).map(|p| p * quantity).ok_or_else(|| Self::not_tradable(kind, quantity))
+    }
+
+    fn get_goods(&self) -> Vec<GoodLabel> {
+        self.book.goods(|kind| (self.unit_buy_price(kind).unwrap_or(0.0), self.unit_sell_price(kind).unwrap_or(0.0)))
+    }
+
+    fn lock_buy(&mut self, kind_to_buy : GoodKind, quantity_to_buy : f32, bid : f32, _trader_name : String) -> Result<String, LockBuyError> {
+        let lowest_acceptable_bid = match self.unit_buy_price(kind_to_buy) {
+            Some(price) => price * quantity_to_buy,
+            None => return Err(LockBuyError::InsufficientGoodQuantityAvailable {
+                requested_good_kind: kind_to_buy,
+                requested_good_quantity: quantity_to_buy,
+                available_good_quantity: 0.0,
+            }),
+        };
+        self.book.lock_buy(kind_to_buy, quantity_to_buy, bid, lowest_acceptable_bid)
+    }
+
+    fn buy(&mut self, token : String, cash : &mut Good) -> Result<Good, BuyError> {
+        self.book.buy(token, cash)
+    }
+
+    fn lock_sell(&mut self, kind_to_sell : GoodKind, quantity_to_sell : f32, offer : f32, _trader_name : String) -> Result<String, LockSellError> {
+        let highest_acceptable_offer = self.unit_sell_price(kind_to_sell).map_or(0.0, |p| p * quantity_to_sell);
+        self.book.lock_sell(kind_to_sell, quantity_to_sell, offer, highest_acceptable_offer)
+    }
+
+    fn sell(&mut self, token : String, good : &mut Good) -> Result<Good, SellError> {
+        self.book.sell(token, good)
+    }
+}
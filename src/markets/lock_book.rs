@@ -0,0 +1,246 @@
+use std::collections::{HashMap, HashSet};
+
+use market_common::event::event::{Event, EventKind};
+use market_common::event::notifiable::Notifiable;
+use market_common::good::good::Good;
+use market_common::good::good_kind::GoodKind;
+use market_common::good::good_kind::GoodKind::*;
+use market_common::market::good_label::GoodLabel;
+use market_common::market::{BuyError, LockBuyError, LockSellError, MarketGetterError, SellError};
+
+//how many days a token stays valid
+static LOCK_VALIDITY_DAYS : u32 = 10;
+static MAX_LOCKS : usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LockSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Lock {
+    pub side: LockSide,
+    pub kind: GoodKind,
+    pub quantity: f32,
+    //the total agreed in EUR
+    pub price: f32,
+    pub expires_on: u32,
+}
+
+//The bookkeeping shared by the markets of this crate that don't come from another group:
+//inventory, locks and tokens, the day counter and the subscribers.
+//The markets only decide the prices; the LockBook checks and settles the trades.
+pub(crate) struct LockBook {
+    inventory: HashMap<GoodKind, f32>,
+    locks: HashMap<String, Lock>,
+    expired: HashSet<String>,
+    next_token: u64,
+    prefix: &'static str,
+    day: u32,
+    subscribers: Vec<Box<dyn Notifiable>>,
+}
+
+impl LockBook {
+
+    pub fn new(prefix : &'static str, inventory : HashMap<GoodKind, f32>) -> Self {
+        let mut inventory = inventory;
+        for kind in [EUR, USD, YEN, YUAN] {
+            inventory.entry(kind).or_insert(0.0);
+        }
+        LockBook { inventory, locks: HashMap::new(), expired: HashSet::new(), next_token: 0, prefix, day: 0, subscribers: Vec::new() }
+    }
+
+    pub fn day(&self) -> u32 {
+        self.day
+    }
+
+    fn reserved(&self, kind : GoodKind) -> f32 {
+        self.locks.values().map(|l| match l.side {
+            LockSide::Buy if l.kind == kind => l.quantity,
+            LockSide::Sell if kind == EUR => l.price,
+            _ => 0.0,
+        }).sum()
+    }
+
+    //what can still be locked: the inventory minus what is already promised to someone
+    pub fn available(&self, kind : GoodKind) -> f32 {
+        (self.inventory.get(&kind).copied().unwrap_or(0.0) - self.reserved(kind)).max(0.0)
+    }
+
+    pub fn budget(&self) -> f32 {
+        self.available(EUR)
+    }
+
+    pub fn set_quantity(&mut self, kind : GoodKind, quantity : f32) {
+        self.inventory.insert(kind, quantity.max(0.0));
+    }
+
+    //`rates` gives the (buy, sell) unit prices of a good
+    pub fn goods(&self, rates : impl Fn(GoodKind) -> (f32, f32)) -> Vec<GoodLabel> {
+        [EUR, USD, YEN, YUAN].iter().map(|kind| {
+            let (exchange_rate_buy, exchange_rate_sell) = if *kind == EUR { (1.0, 1.0) } else { rates(*kind) };
+            GoodLabel { good_kind: *kind, quantity: self.available(*kind), exchange_rate_buy, exchange_rate_sell }
+        }).collect()
+    }
+
+    //the checks every price getter has to do before quoting
+    pub fn check_quote(&self, kind : GoodKind, quantity : f32) -> Result<(), MarketGetterError> {
+        if quantity <= 0.0 {
+            return Err(MarketGetterError::NonPositiveQuantityAsked);
+        }
+        let available = self.available(kind);
+        if quantity > available {
+            return Err(MarketGetterError::InsufficientGoodQuantityAvailable {
+                requested_good_kind: kind,
+                requested_good_quantity: quantity,
+                available_good_quantity: available,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn lock(&self, token : &str) -> Option<Lock> {
+        self.locks.get(token).copied()
+    }
+
+    fn new_token(&mut self) -> String {
+        self.next_token += 1;
+        format!("{}-{}", self.prefix, self.next_token)
+    }
+
+    //`lowest_acceptable_bid` is the market's own total price for the quantity
+    pub fn lock_buy(&mut self, kind : GoodKind, quantity : f32, bid : f32, lowest_acceptable_bid : f32) -> Result<String, LockBuyError> {
+        if quantity <= 0.0 {
+            return Err(LockBuyError::NonPositiveQuantityToBuy { negative_quantity_to_buy: quantity });
+        }
+        if bid <= 0.0 {
+            return Err(LockBuyError::NonPositiveBid { negative_bid: bid });
+        }
+        if self.locks.len() >= MAX_LOCKS {
+            return Err(LockBuyError::MaxAllowedLocksReached);
+        }
+        let available = self.available(kind);
+        if quantity > available {
+            return Err(LockBuyError::InsufficientGoodQuantityAvailable {
+                requested_good_kind: kind,
+                requested_good_quantity: quantity,
+                available_good_quantity: available,
+            });
+        }
+        if bid < lowest_acceptable_bid {
+            return Err(LockBuyError::BidTooLow {
+                requested_good_kind: kind,
+                requested_good_quantity: quantity,
+                low_bid: bid,
+                lowest_acceptable_bid,
+            });
+        }
+
+        let token = self.new_token();
+        self.locks.insert(token.clone(), Lock { side: LockSide::Buy, kind, quantity, price: bid, expires_on: self.day + LOCK_VALIDITY_DAYS });
+        self.notify(EventKind::LockedBuy, kind, quantity, bid);
+        Ok(token)
+    }
+
+    pub fn buy(&mut self, token : String, cash : &mut Good) -> Result<Good, BuyError> {
+        if self.expired.contains(&token) {
+            return Err(BuyError::ExpiredToken { expired_token: token });
+        }
+        let lock = match self.locks.get(&token) {
+            Some(lock) if lock.side == LockSide::Buy => *lock,
+            _ => return Err(BuyError::UnrecognizedToken { unrecognized_token: token }),
+        };
+        if cash.get_kind() != EUR {
+            return Err(BuyError::GoodKindNotDefault { non_default_good_kind: cash.get_kind() });
+        }
+        if cash.get_qty() < lock.price {
+            return Err(BuyError::InsufficientGoodQuantity { contained_quantity: cash.get_qty(), pre_agreed_quantity: lock.price });
+        }
+
+        let _ = cash.split(lock.price);
+        self.locks.remove(&token);
+        *self.inventory.entry(EUR).or_insert(0.0) += lock.price;
+        *self.inventory.entry(lock.kind).or_insert(0.0) -= lock.quantity;
+        self.notify(EventKind::Bought, lock.kind, lock.quantity, lock.price);
+        Ok(Good::new(lock.kind, lock.quantity))
+    }
+
+    //`highest_acceptable_offer` is the market's own total price for the quantity
+    pub fn lock_sell(&mut self, kind : GoodKind, quantity : f32, offer : f32, highest_acceptable_offer : f32) -> Result<String, LockSellError> {
+        if quantity <= 0.0 {
+            return Err(LockSellError::NonPositiveQuantityToSell { negative_quantity_to_sell: quantity });
+        }
+        if offer <= 0.0 {
+            return Err(LockSellError::NonPositiveOffer { negative_offer: offer });
+        }
+        if self.locks.len() >= MAX_LOCKS {
+            return Err(LockSellError::MaxAllowedLocksReached);
+        }
+        let available = self.budget();
+        if offer > available {
+            return Err(LockSellError::InsufficientDefaultGoodQuantityAvailable {
+                offered_good_kind: kind,
+                offered_good_quantity: quantity,
+                available_good_quantity: available,
+            });
+        }
+        if offer > highest_acceptable_offer {
+            return Err(LockSellError::OfferTooHigh {
+                offered_good_kind: kind,
+                offered_good_quantity: quantity,
+                high_offer: offer,
+                highest_acceptable_offer,
+            });
+        }
+
+        let token = self.new_token();
+        self.locks.insert(token.clone(), Lock { side: LockSide::Sell, kind, quantity, price: offer, expires_on: self.day + LOCK_VALIDITY_DAYS });
+        self.notify(EventKind::LockedSell, kind, quantity, offer);
+        Ok(token)
+    }
+
+    pub fn sell(&mut self, token : String, good : &mut Good) -> Result<Good, SellError> {
+        if self.expired.contains(&token) {
+            return Err(SellError::ExpiredToken { expired_token: token });
+        }
+        let lock = match self.locks.get(&token) {
+            Some(lock) if lock.side == LockSide::Sell => *lock,
+            _ => return Err(SellError::UnrecognizedToken { unrecognized_token: token }),
+        };
+        if good.get_kind() != lock.kind {
+            return Err(SellError::WrongGoodKind { wrong_good_kind: good.get_kind(), pre_agreed_kind: lock.kind });
+        }
+        if good.get_qty() < lock.quantity {
+            return Err(SellError::InsufficientGoodQuantity { contained_quantity: good.get_qty(), pre_agreed_quantity: lock.quantity });
+        }
+
+        let _ = good.split(lock.quantity);
+        self.locks.remove(&token);
+        *self.inventory.entry(lock.kind).or_insert(0.0) += lock.quantity;
+        *self.inventory.entry(EUR).or_insert(0.0) -= lock.price;
+        self.notify(EventKind::Sold, lock.kind, lock.quantity, lock.price);
+        Ok(Good::new(EUR, lock.price))
+    }
+
+    //the tokens that ran out of time are kept, so that using them gives ExpiredToken instead of UnrecognizedToken
+    pub fn advance_day(&mut self) {
+        self.day += 1;
+        let day = self.day;
+        let expired: Vec<String> = self.locks.iter().filter(|(_, l)| l.expires_on <= day).map(|(t, _)| t.clone()).collect();
+        for token in expired {
+            self.locks.remove(&token);
+            self.expired.insert(token);
+        }
+    }
+
+    pub fn add_subscriber(&mut self, subscriber : Box<dyn Notifiable>) {
+        self.subscribers.push(subscriber);
+    }
+
+    fn notify(&mut self, kind : EventKind, good_kind : GoodKind, quantity : f32, price : f32) {
+        for subscriber in self.subscribers.iter_mut() {
+            subscriber.on_event(Event { kind: kind.clone(), good_kind, quantity, price });
+        }
+    }
+}
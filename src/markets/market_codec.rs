@@ -0,0 +1,326 @@
+//JSON encoding of the market_common types, which don't implement serde themselves.
+//Every decoder returns None when the value doesn't look like what it should.
+
+use serde_json::{json, Value};
+
+use market_common::event::event::{Event, EventKind};
+use market_common::good::good::Good;
+use market_common::good::good_kind::GoodKind;
+use market_common::good::good_kind::GoodKind::*;
+use market_common::market::good_label::GoodLabel;
+use market_common::market::{BuyError, LockBuyError, LockSellError, MarketGetterError, SellError};
+
+pub fn good_kind(kind : GoodKind) -> Value {
+    json!(match kind {
+        EUR => "EUR",
+        USD => "USD",
+        YEN => "YEN",
+        YUAN => "YUAN",
+    })
+}
+
+pub fn parse_good_kind(value : &Value) -> Option<GoodKind> {
+    match value.as_str()? {
+        "EUR" => Some(EUR),
+        "USD" => Some(USD),
+        "YEN" => Some(YEN),
+        "YUAN" => Some(YUAN),
+        _ => None,
+    }
+}
+
+pub fn parse_f32(value : &Value) -> Option<f32> {
+    value.as_f64().map(|v| v as f32)
+}
+
+fn field_f32(value : &Value, name : &str) -> Option<f32> {
+    parse_f32(value.get(name)?)
+}
+
+fn field_kind(value : &Value, name : &str) -> Option<GoodKind> {
+    parse_good_kind(value.get(name)?)
+}
+
+fn field_string(value : &Value, name : &str) -> Option<String> {
+    value.get(name)?.as_str().map(|s| s.to_string())
+}
+
+fn variant(value : &Value) -> Option<&str> {
+    value.get("variant")?.as_str()
+}
+
+pub fn good(good : &Good) -> Value {
+    json!({ "kind": good_kind(good.get_kind()), "quantity": good.get_qty() })
+}
+
+pub fn parse_good(value : &Value) -> Option<Good> {
+    Some(Good::new(field_kind(value, "kind")?, field_f32(value, "quantity")?))
+}
+
+pub fn good_labels(labels : &[GoodLabel]) -> Value {
+    Value::Array(labels.iter().map(|l| json!({
+        "good_kind": good_kind(l.good_kind),
+        "quantity": l.quantity,
+        "exchange_rate_buy": l.exchange_rate_buy,
+        "exchange_rate_sell": l.exchange_rate_sell,
+    })).collect())
+}
+
+pub fn parse_good_labels(value : &Value) -> Option<Vec<GoodLabel>> {
+    value.as_array()?.iter().map(|l| Some(GoodLabel {
+        good_kind: field_kind(l, "good_kind")?,
+        quantity: field_f32(l, "quantity")?,
+        exchange_rate_buy: field_f32(l, "exchange_rate_buy")?,
+        exchange_rate_sell: field_f32(l, "exchange_rate_sell")?,
+    })).collect()
+}
+
+//{"ok": ...} or {"err": ...}
+pub fn result<T, E>(result : &Result<T, E>, ok : impl Fn(&T) -> Value, err : impl Fn(&E) -> Value) -> Value {
+    match result {
+        Ok(t) => json!({ "ok": ok(t) }),
+        Err(e) => json!({ "err": err(e) }),
+    }
+}
+
+pub fn parse_result<T, E>(value : &Value, ok : impl Fn(&Value) -> Option<T>, err : impl Fn(&Value) -> Option<E>) -> Option<Result<T, E>> {
+    if let Some(v) = value.get("ok") {
+        return ok(v).map(Ok);
+    }
+    err(value.get("err")?).map(Err)
+}
+
+pub fn getter_error(e : &MarketGetterError) -> Value {
+    match e {
+        MarketGetterError::NonPositiveQuantityAsked => json!({ "variant": "NonPositiveQuantityAsked" }),
+        MarketGetterError::InsufficientGoodQuantityAvailable { requested_good_kind, requested_good_quantity, available_good_quantity } => json!({
+            "variant": "InsufficientGoodQuantityAvailable",
+            "requested_good_kind": good_kind(*requested_good_kind),
+            "requested_good_quantity": requested_good_quantity,
+            "available_good_quantity": available_good_quantity,
+        }),
+    }
+}
+
+pub fn parse_getter_error(v : &Value) -> Option<MarketGetterError> {
+    match variant(v)? {
+        "NonPositiveQuantityAsked" => Some(MarketGetterError::NonPositiveQuantityAsked),
+        "InsufficientGoodQuantityAvailable" => Some(MarketGetterError::InsufficientGoodQuantityAvailable {
+            requested_good_kind: field_kind(v, "requested_good_kind")?,
+            requested_good_quantity: field_f32(v, "requested_good_quantity")?,
+            available_good_quantity: field_f32(v, "available_good_quantity")?,
+        }),
+        _ => None,
+    }
+}
+
+pub fn lock_buy_error(e : &LockBuyError) -> Value {
+    match e {
+        LockBuyError::NonPositiveQuantityToBuy { negative_quantity_to_buy } => json!({
+            "variant": "NonPositiveQuantityToBuy",
+            "negative_quantity_to_buy": negative_quantity_to_buy,
+        }),
+        LockBuyError::NonPositiveBid { negative_bid } => json!({
+            "variant": "NonPositiveBid",
+            "negative_bid": negative_bid,
+        }),
+        LockBuyError::MaxAllowedLocksReached => json!({ "variant": "MaxAllowedLocksReached" }),
+        LockBuyError::InsufficientGoodQuantityAvailable { requested_good_kind, requested_good_quantity, available_good_quantity } => json!({
+            "variant": "InsufficientGoodQuantityAvailable",
+            "requested_good_kind": good_kind(*requested_good_kind),
+            "requested_good_quantity": requested_good_quantity,
+            "available_good_quantity": available_good_quantity,
+        }),
+        LockBuyError::BidTooLow { requested_good_kind, requested_good_quantity, low_bid, lowest_acceptable_bid } => json!({
+            "variant": "BidTooLow",
+            "requested_good_kind": good_kind(*requested_good_kind),
+            "requested_good_quantity": requested_good_quantity,
+            "low_bid": low_bid,
+            "lowest_acceptable_bid": lowest_acceptable_bid,
+        }),
+    }
+}
+
+pub fn parse_lock_buy_error(v : &Value) -> Option<LockBuyError> {
+    match variant(v)? {
+        "NonPositiveQuantityToBuy" => Some(LockBuyError::NonPositiveQuantityToBuy {
+            negative_quantity_to_buy: field_f32(v, "negative_quantity_to_buy")?,
+        }),
+        "NonPositiveBid" => Some(LockBuyError::NonPositiveBid {
+            negative_bid: field_f32(v, "negative_bid")?,
+        }),
+        "MaxAllowedLocksReached" => Some(LockBuyError::MaxAllowedLocksReached),
+        "InsufficientGoodQuantityAvailable" => Some(LockBuyError::InsufficientGoodQuantityAvailable {
+            requested_good_kind: field_kind(v, "requested_good_kind")?,
+            requested_good_quantity: field_f32(v, "requested_good_quantity")?,
+            available_good_quantity: field_f32(v, "available_good_quantity")?,
+        }),
+        "BidTooLow" => Some(LockBuyError::BidTooLow {
+            requested_good_kind: field_kind(v, "requested_good_kind")?,
+            requested_good_quantity: field_f32(v, "requested_good_quantity")?,
+            low_bid: field_f32(v, "low_bid")?,
+            lowest_acceptable_bid: field_f32(v, "lowest_acceptable_bid")?,
+        }),
+        _ => None,
+    }
+}
+
+pub fn buy_error(e : &BuyError) -> Value {
+    match e {
+        BuyError::UnrecognizedToken { unrecognized_token } => json!({
+            "variant": "UnrecognizedToken",
+            "unrecognized_token": unrecognized_token,
+        }),
+        BuyError::ExpiredToken { expired_token } => json!({
+            "variant": "ExpiredToken",
+            "expired_token": expired_token,
+        }),
+        BuyError::GoodKindNotDefault { non_default_good_kind } => json!({
+            "variant": "GoodKindNotDefault",
+            "non_default_good_kind": good_kind(*non_default_good_kind),
+        }),
+        BuyError::InsufficientGoodQuantity { contained_quantity, pre_agreed_quantity } => json!({
+            "variant": "InsufficientGoodQuantity",
+            "contained_quantity": contained_quantity,
+            "pre_agreed_quantity": pre_agreed_quantity,
+        }),
+    }
+}
+
+pub fn parse_buy_error(v : &Value) -> Option<BuyError> {
+    match variant(v)? {
+        "UnrecognizedToken" => Some(BuyError::UnrecognizedToken { unrecognized_token: field_string(v, "unrecognized_token")? }),
+        "ExpiredToken" => Some(BuyError::ExpiredToken { expired_token: field_string(v, "expired_token")? }),
+        "GoodKindNotDefault" => Some(BuyError::GoodKindNotDefault { non_default_good_kind: field_kind(v, "non_default_good_kind")? }),
+        "InsufficientGoodQuantity" => Some(BuyError::InsufficientGoodQuantity {
+            contained_quantity: field_f32(v, "contained_quantity")?,
+            pre_agreed_quantity: field_f32(v, "pre_agreed_quantity")?,
+        }),
+        _ => None,
+    }
+}
+
+pub fn lock_sell_error(e : &LockSellError) -> Value {
+    match e {
+        LockSellError::NonPositiveQuantityToSell { negative_quantity_to_sell } => json!({
+            "variant": "NonPositiveQuantityToSell",
+            "negative_quantity_to_sell": negative_quantity_to_sell,
+        }),
+        LockSellError::NonPositiveOffer { negative_offer } => json!({
+            "variant": "NonPositiveOffer",
+            "negative_offer": negative_offer,
+        }),
+        LockSellError::DefaultGoodAlreadyLocked { token } => json!({
+            "variant": "DefaultGoodAlreadyLocked",
+            "token": token,
+        }),
+        LockSellError::MaxAllowedLocksReached => json!({ "variant": "MaxAllowedLocksReached" }),
+        LockSellError::InsufficientDefaultGoodQuantityAvailable { offered_good_kind, offered_good_quantity, available_good_quantity } => json!({
+            "variant": "InsufficientDefaultGoodQuantityAvailable",
+            "offered_good_kind": good_kind(*offered_good_kind),
+            "offered_good_quantity": offered_good_quantity,
+            "available_good_quantity": available_good_quantity,
+        }),
+        LockSellError::OfferTooHigh { offered_good_kind, offered_good_quantity, high_offer, highest_acceptable_offer } => json!({
+            "variant": "OfferTooHigh",
+            "offered_good_kind": good_kind(*offered_good_kind),
+            "offered_good_quantity": offered_good_quantity,
+            "high_offer": high_offer,
+            "highest_acceptable_offer": highest_acceptable_offer,
+        }),
+    }
+}
+
+pub fn parse_lock_sell_error(v : &Value) -> Option<LockSellError> {
+    match variant(v)? {
+        "NonPositiveQuantityToSell" => Some(LockSellError::NonPositiveQuantityToSell {
+            negative_quantity_to_sell: field_f32(v, "negative_quantity_to_sell")?,
+        }),
+        "NonPositiveOffer" => Some(LockSellError::NonPositiveOffer { negative_offer: field_f32(v, "negative_offer")? }),
+        "DefaultGoodAlreadyLocked" => Some(LockSellError::DefaultGoodAlreadyLocked { token: field_string(v, "token")? }),
+        "MaxAllowedLocksReached" => Some(LockSellError::MaxAllowedLocksReached),
+        "InsufficientDefaultGoodQuantityAvailable" => Some(LockSellError::InsufficientDefaultGoodQuantityAvailable {
+            offered_good_kind: field_kind(v, "offered_good_kind")?,
+            offered_good_quantity: field_f32(v, "offered_good_quantity")?,
+            available_good_quantity: field_f32(v, "available_good_quantity")?,
+        }),
+        "OfferTooHigh" => Some(LockSellError::OfferTooHigh {
+            offered_good_kind: field_kind(v, "offered_good_kind")?,
+            offered_good_quantity: field_f32(v, "offered_good_quantity")?,
+            high_offer: field_f32(v, "high_offer")?,
+            highest_acceptable_offer: field_f32(v, "highest_acceptable_offer")?,
+        }),
+        _ => None,
+    }
+}
+
+pub fn sell_error(e : &SellError) -> Value {
+    match e {
+        SellError::UnrecognizedToken { unrecognized_token } => json!({
+            "variant": "UnrecognizedToken",
+            "unrecognized_token": unrecognized_token,
+        }),
+        SellError::ExpiredToken { expired_token } => json!({
+            "variant": "ExpiredToken",
+            "expired_token": expired_token,
+        }),
+        SellError::WrongGoodKind { wrong_good_kind, pre_agreed_kind } => json!({
+            "variant": "WrongGoodKind",
+            "wrong_good_kind": good_kind(*wrong_good_kind),
+            "pre_agreed_kind": good_kind(*pre_agreed_kind),
+        }),
+        SellError::InsufficientGoodQuantity { contained_quantity, pre_agreed_quantity } => json!({
+            "variant": "InsufficientGoodQuantity",
+            "contained_quantity": contained_quantity,
+            "pre_agreed_quantity": pre_agreed_quantity,
+        }),
+    }
+}
+
+pub fn parse_sell_error(v : &Value) -> Option<SellError> {
+    match variant(v)? {
+        "UnrecognizedToken" => Some(SellError::UnrecognizedToken { unrecognized_token: field_string(v, "unrecognized_token")? }),
+        "ExpiredToken" => Some(SellError::ExpiredToken { expired_token: field_string(v, "expired_token")? }),
+        "WrongGoodKind" => Some(SellError::WrongGoodKind {
+            wrong_good_kind: field_kind(v, "wrong_good_kind")?,
+            pre_agreed_kind: field_kind(v, "pre_agreed_kind")?,
+        }),
+        "InsufficientGoodQuantity" => Some(SellError::InsufficientGoodQuantity {
+            contained_quantity: field_f32(v, "contained_quantity")?,
+            pre_agreed_quantity: field_f32(v, "pre_agreed_quantity")?,
+        }),
+        _ => None,
+    }
+}
+
+pub fn event(e : &Event) -> Value {
+    json!({
+        "kind": match e.kind {
+            EventKind::Bought => "Bought",
+            EventKind::Sold => "Sold",
+            EventKind::LockedBuy => "LockedBuy",
+            EventKind::LockedSell => "LockedSell",
+            EventKind::Wait => "Wait",
+        },
+        "good_kind": good_kind(e.good_kind),
+        "quantity": e.quantity,
+        "price": e.price,
+    })
+}
+
+pub fn parse_event(v : &Value) -> Option<Event> {
+    let kind = match v.get("kind")?.as_str()? {
+        "Bought" => EventKind::Bought,
+        "Sold" => EventKind::Sold,
+        "LockedBuy" => EventKind::LockedBuy,
+        "LockedSell" => EventKind::LockedSell,
+        "Wait" => EventKind::Wait,
+        _ => return None,
+    };
+    Some(Event {
+        kind,
+        good_kind: field_kind(v, "good_kind")?,
+        quantity: field_f32(v, "quantity")?,
+        price: field_f32(v, "price")?,
+    })
+}
@@ -0,0 +1,179 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use serde_json::{json, Value};
+
+use market_common::event::event::Event;
+use market_common::event::notifiable::Notifiable;
+use market_common::good::good::Good;
+use market_common::good::good_kind::GoodKind;
+use market_common::market::good_label::GoodLabel;
+use market_common::market::{BuyError, LockBuyError, LockSellError, Market, MarketGetterError, SellError};
+
+use crate::markets::market_codec as codec;
+
+//Wraps a market and writes every call made to it, together with the market's answer, to a JSON-lines file.
+//The first line is a header, every other line is {"call": ..., "args": ..., "response": ..., "broadcast": [...]},
+//where "broadcast" holds the events the market sent to its subscribers while answering.
+//Feed the file to ReplayMarket::load() to get the very same answers (and events) back.
+pub struct RecordingMarket {
+    inner: Rc<RefCell<dyn Market>>,
+    writer: RefCell<LineWriter<File>>,
+    //what the inner market told its subscribers since the last recorded call
+    broadcasts: Rc<RefCell<Vec<Event>>>,
+    //the first write that failed. Nothing is recorded after it: a recording with a hole couldn't be replayed anyway.
+    error: RefCell<Option<io::Error>>,
+}
+
+//subscribed to the inner market, to see the events it broadcasts
+struct BroadcastTap {
+    events: Rc<RefCell<Vec<Event>>>,
+}
+
+impl Notifiable for BroadcastTap {
+    //nobody listens to the tap
+    fn add_subscriber(&mut self, _subscriber : Box<dyn Notifiable>) {}
+
+    fn on_event(&mut self, event : Event) {
+        self.events.borrow_mut().push(event);
+    }
+}
+
+impl RecordingMarket {
+
+    pub fn wrap(inner : Rc<RefCell<dyn Market>>, path : impl AsRef<Path>) -> io::Result<Rc<RefCell<RecordingMarket>>> {
+        Self::wrap_with_header(inner, path, json!({}))
+    }
+
+    //`header` is merged into the first line of the file, e.g. to keep track of how the inner market was built
+    pub fn wrap_with_header(inner : Rc<RefCell<dyn Market>>, path : impl AsRef<Path>, header : Value) -> io::Result<Rc<RefCell<RecordingMarket>>> {
+        let mut writer = LineWriter::new(File::create(path)?);
+
+        let mut first_line = json!({ "name": inner.borrow().get_name() });
+        if let (Some(line), Value::Object(extra)) = (first_line.as_object_mut(), header) {
+            line.extend(extra);
+        }
+        writeln!(writer, "{}", json!({ "header": first_line }))?;
+
+        let broadcasts = Rc::new(RefCell::new(Vec::new()));
+        inner.borrow_mut().add_subscriber(Box::new(BroadcastTap { events: Rc::clone(&broadcasts) }));
+
+        Ok(Rc::new(RefCell::new(RecordingMarket { inner, writer: RefCell::new(writer), broadcasts, error: RefCell::new(None) })))
+    }
+
+    //Err if a call couldn't be written: the recording stops right before it
+    pub fn status(&self) -> io::Result<()> {
+        match &*self.error.borrow() {
+            Some(e) => Err(io::Error::new(e.kind(), e.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    //a broken recording must not break the market: the error is kept for status() and the trading goes on
+    fn record(&self, call : &str, args : Value, response : Value) {
+        let broadcast: Vec<Value> = self.broadcasts.borrow_mut().drain(..).map(|e| codec::event(&e)).collect();
+        if self.error.borrow().is_some() {
+            return;
+        }
+        let line = json!({ "call": call, "args": args, "response": response, "broadcast": broadcast });
+        if let Err(e) = writeln!(self.writer.borrow_mut(), "{}", line) {
+            *self.error.borrow_mut() = Some(e);
+        }
+    }
+}
+
+impl Notifiable for RecordingMarket {
+    fn add_subscriber(&mut self, subscriber : Box<dyn Notifiable>) {
+        self.inner.borrow_mut().add_subscriber(subscriber);
+    }
+
+    fn on_event(&mut self, event : Event) {
+        self.record("on_event", codec::event(&event), Value::Null);
+        self.inner.borrow_mut().on_event(event);
+    }
+}
+
+//The constructors can't know which market to record, nor where: use wrap()
+impl Market for RecordingMarket {
+    fn new_random() -> Rc<RefCell<dyn Market>> where Self: Sized {
+        panic!("A RecordingMarket records a market built elsewhere: use RecordingMarket::wrap()");
+    }
+
+    fn new_with_quantities(_ : f32, _ : f32, _ : f32, _ : f32) -> Rc<RefCell<dyn Market>> where Self: Sized {
+        panic!("A RecordingMarket records a market built elsewhere: use RecordingMarket::wrap()");
+    }
+
+    fn new_file(_ : &str) -> Rc<RefCell<dyn Market>> where Self: Sized {
+        panic!("A RecordingMarket records a market built elsewhere: use RecordingMarket::wrap()");
+    }
+
+    fn get_name(&self) -> &'static str {
+        self.inner.borrow().get_name()
+    }
+
+    fn get_budget(&self) -> f32 {
+        let budget = self.inner.borrow().get_budget();
+        self.record("get_budget", Value::Null, json!(budget));
+        budget
+    }
+
+    fn get_buy_price(&self, kind : GoodKind, quantity : f32) -> Result<f32, MarketGetterError> {
+        let res = self.inner.borrow().get_buy_price(kind, quantity);
+        self.record("get_buy_price",
+                    json!({ "kind": codec::good_kind(kind), "quantity": quantity }),
+                    codec::result(&res, |p| json!(p), codec::getter_error));
+        res
+    }
+
+    fn get_sell_price(&self, kind : GoodKind, quantity : f32) -> Result<f32, MarketGetterError> {
+        let res = self.inner.borrow().get_sell_price(kind, quantity);
+        self.record("get_sell_price",
+                    json!({ "kind": codec::good_kind(kind), "quantity": quantity }),
+                    codec::result(&res, |p| json!(p), codec::getter_error));
+        res
+    }
+
+    fn get_goods(&self) -> Vec<GoodLabel> {
+        let goods = self.inner.borrow().get_goods();
+        self.record("get_goods", Value::Null, codec::good_labels(&goods));
+        goods
+    }
+
+    fn lock_buy(&mut self, kind_to_buy : GoodKind, quantity_to_buy : f32, bid : f32, trader_name : String) -> Result<String, LockBuyError> {
+        let args = json!({ "kind": codec::good_kind(kind_to_buy), "quantity": quantity_to_buy, "bid": bid, "trader_name": trader_name });
+        let res = self.inner.borrow_mut().lock_buy(kind_to_buy, quantity_to_buy, bid, trader_name);
+        self.record("lock_buy", args, codec::result(&res, |t| json!(t), codec::lock_buy_error));
+        res
+    }
+
+    fn buy(&mut self, token : String, cash : &mut Good) -> Result<Good, BuyError> {
+        let args = json!({ "token": token, "cash": codec::good(cash) });
+        let res = self.inner.borrow_mut().buy(token, cash);
+        self.record("buy", args, json!({
+            "result": codec::result(&res, codec::good, codec::buy_error),
+            "cash_after": cash.get_qty(),
+        }));
+        res
+    }
+
+    fn lock_sell(&mut self, kind_to_sell : GoodKind, quantity_to_sell : f32, offer : f32, trader_name : String) -> Result<String, LockSellError> {
+        let args = json!({ "kind": codec::good_kind(kind_to_sell), "quantity": quantity_to_sell, "offer": offer, "trader_name": trader_name });
+        let res = self.inner.borrow_mut().lock_sell(kind_to_sell, quantity_to_sell, offer, trader_name);
+        self.record("lock_sell", args, codec::result(&res, |t| json!(t), codec::lock_sell_error));
+        res
+    }
+
+    fn sell(&mut self, token : String, good : &mut Good) -> Result<Good, SellError> {
+        let args = json!({ "token": token, "good": codec::good(good) });
+        let res = self.inner.borrow_mut().sell(token, good);
+        self.record("sell", args, json!({
+            "result": codec::result(&res, codec::good, codec::sell_error),
+            "good_after": good.get_qty(),
+        }));
+        res
+    }
+}
@@ -0,0 +1,220 @@
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use serde_json::{json, Value};
+
+use market_common::event::event::Event;
+use market_common::event::notifiable::Notifiable;
+use market_common::good::good::Good;
+use market_common::good::good_kind::GoodKind;
+use market_common::market::good_label::GoodLabel;
+use market_common::market::{BuyError, LockBuyError, LockSellError, Market, MarketGetterError, SellError};
+
+use crate::markets::market_codec as codec;
+
+//Serves back the answers stored by a RecordingMarket, in the same order.
+//The calls have to be exactly the ones that were recorded: as soon as the strategy does something different
+//the replay panics, telling which call diverged.
+pub struct ReplayMarket {
+    name: &'static str,
+    header: Value,
+    entries: Vec<Value>,
+    cursor: Cell<usize>,
+    //they get the events the recorded market broadcast, after the same calls
+    subscribers: Vec<Box<dyn Notifiable>>,
+}
+
+fn invalid_data(msg : String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+//the numbers are f32 in the markets: comparing them as f64 after a trip through the file would be too strict
+fn same_args(recorded : &Value, actual : &Value) -> bool {
+    match (recorded, actual) {
+        (Value::Number(a), Value::Number(b)) => codec::parse_f32(recorded) == codec::parse_f32(actual) || a == b,
+        (Value::Array(a), Value::Array(b)) => a.len() == b.len() && a.iter().zip(b).all(|(x, y)| same_args(x, y)),
+        (Value::Object(a), Value::Object(b)) => a.len() == b.len() && a.iter().all(|(k, x)| b.get(k).map_or(false, |y| same_args(x, y))),
+        _ => recorded == actual,
+    }
+}
+
+impl ReplayMarket {
+
+    pub fn load(path : impl AsRef<Path>) -> io::Result<Rc<RefCell<dyn Market>>> {
+        Ok(Rc::new(RefCell::new(Self::from_file(path)?)))
+    }
+
+    pub fn from_file(path : impl AsRef<Path>) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let mut lines = content.lines().filter(|l| !l.trim().is_empty());
+
+        let header: Value = serde_json::from_str(lines.next().ok_or_else(|| invalid_data("empty recording".to_string()))?)
+            .map_err(|e| invalid_data(e.to_string()))?;
+        let header = header.get("header").cloned().ok_or_else(|| invalid_data("the recording has no header".to_string()))?;
+
+        let entries = lines
+            .map(|l| serde_json::from_str(l).map_err(|e| invalid_data(e.to_string())))
+            .collect::<io::Result<Vec<Value>>>()?;
+
+        //get_name() must return a &'static str. A replayed market lives as long as the simulation anyway.
+        let name: &'static str = Box::leak(header.get("name").and_then(|n| n.as_str()).unwrap_or("REPLAY").to_string().into_boxed_str());
+
+        Ok(ReplayMarket { name, header, entries, cursor: Cell::new(0), subscribers: Vec::new() })
+    }
+
+    //whatever was written in the header by the recorder (name, seed...)
+    pub fn header(&self) -> &Value {
+        &self.header
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    pub fn remaining_calls(&self) -> usize {
+        self.entries.len() - self.cursor.get()
+    }
+
+    fn next(&self, call : &str, args : &Value) -> &Value {
+        let index = self.cursor.get();
+        let entry = self.entries.get(index)
+            .unwrap_or_else(|| panic!("Replay of \"{}\" ran out of recorded calls at call #{} ({} {})", self.name, index, call, args));
+
+        let recorded_call = entry.get("call").and_then(|c| c.as_str()).unwrap_or("");
+        let recorded_args = entry.get("args").unwrap_or(&Value::Null);
+        if recorded_call != call || !same_args(recorded_args, args) {
+            panic!("Replay of \"{}\" diverged at call #{}: recorded {} {}, got {} {}", self.name, index, recorded_call, recorded_args, call, args);
+        }
+
+        self.cursor.set(index + 1);
+        entry.get("response").unwrap_or(&Value::Null)
+    }
+
+    //sends the subscribers what the recorded market sent them while answering the call just served.
+    //Only the calls that change the market broadcast anything: the getters are never replayed here.
+    fn rebroadcast(&mut self, call : &str) {
+        let index = self.cursor.get() - 1;
+        let events = match self.entries[index].get("broadcast").and_then(|b| b.as_array()) {
+            Some(events) => events.clone(),
+            None => return,
+        };
+        if events.iter().any(|e| codec::parse_event(e).is_none()) {
+            self.corrupted(call);
+        }
+        for value in events.iter() {
+            for subscriber in self.subscribers.iter_mut() {
+                if let Some(event) = codec::parse_event(value) {
+                    subscriber.on_event(event);
+                }
+            }
+        }
+    }
+
+    fn corrupted(&self, call : &str) -> ! {
+        panic!("Replay of \"{}\": the recorded response to call #{} ({}) is corrupted", self.name, self.cursor.get() - 1, call)
+    }
+
+    //moves `good` to the quantity it had after the recorded call
+    fn apply_recorded_quantity(good : &mut Good, after : f32) {
+        let diff = good.get_qty() - after;
+        if diff > 0.0 {
+            let _ = good.split(diff);
+        } else if diff < 0.0 {
+            let _ = good.merge(Good::new(good.get_kind(), -diff));
+        }
+    }
+}
+
+impl Notifiable for ReplayMarket {
+    fn add_subscriber(&mut self, subscriber : Box<dyn Notifiable>) {
+        self.subscribers.push(subscriber);
+    }
+
+    fn on_event(&mut self, event : Event) {
+        self.next("on_event", &codec::event(&event));
+        self.rebroadcast("on_event");
+    }
+}
+
+impl Market for ReplayMarket {
+    fn new_random() -> Rc<RefCell<dyn Market>> where Self: Sized {
+        panic!("A ReplayMarket can only be built from a recording: use ReplayMarket::new_file()");
+    }
+
+    fn new_with_quantities(_ : f32, _ : f32, _ : f32, _ : f32) -> Rc<RefCell<dyn Market>> where Self: Sized {
+        panic!("A ReplayMarket can only be built from a recording: use ReplayMarket::new_file()");
+    }
+
+    fn new_file(path : &str) -> Rc<RefCell<dyn Market>> where Self: Sized {
+        Self::load(path).unwrap_or_else(|e| panic!("Couldn't load the recording \"{}\": {}", path, e))
+    }
+
+    fn get_name(&self) -> &'static str {
+        self.name
+    }
+
+    fn get_budget(&self) -> f32 {
+        let response = self.next("get_budget", &Value::Null);
+        codec::parse_f32(response).unwrap_or_else(|| self.corrupted("get_budget"))
+    }
+
+    fn get_buy_price(&self, kind : GoodKind, quantity : f32) -> Result<f32, MarketGetterError> {
+        let response = self.next("get_buy_price", &json!({ "kind": codec::good_kind(kind), "quantity": quantity }));
+        codec::parse_result(response, codec::parse_f32, codec::parse_getter_error).unwrap_or_else(|| self.corrupted("get_buy_price"))
+    }
+
+    fn get_sell_price(&self, kind : GoodKind, quantity : f32) -> Result<f32, MarketGetterError> {
+        let response = self.next("get_sell_price", &json!({ "kind": codec::good_kind(kind), "quantity": quantity }));
+        codec::parse_result(response, codec::parse_f32, codec::parse_getter_error).unwrap_or_else(|| self.corrupted("get_sell_price"))
+    }
+
+    fn get_goods(&self) -> Vec<GoodLabel> {
+        let response = self.next("get_goods", &Value::Null);
+        codec::parse_good_labels(response).unwrap_or_else(|| self.corrupted("get_goods"))
+    }
+
+    fn lock_buy(&mut self, kind_to_buy : GoodKind, quantity_to_buy : f32, bid : f32, trader_name : String) -> Result<String, LockBuyError> {
+        let args = json!({ "kind": codec::good_kind(kind_to_buy), "quantity": quantity_to_buy, "bid": bid, "trader_name": trader_name });
+        let response = self.next("lock_buy", &args);
+        let res = codec::parse_result(response, |t| t.as_str().map(|t| t.to_string()), codec::parse_lock_buy_error)
+            .unwrap_or_else(|| self.corrupted("lock_buy"));
+        self.rebroadcast("lock_buy");
+        res
+    }
+
+    fn buy(&mut self, token : String, cash : &mut Good) -> Result<Good, BuyError> {
+        let response = self.next("buy", &json!({ "token": token, "cash": codec::good(cash) }));
+        let res = response.get("result")
+            .and_then(|r| codec::parse_result(r, codec::parse_good, codec::parse_buy_error))
+            .unwrap_or_else(|| self.corrupted("buy"));
+        let cash_after = response.get("cash_after").and_then(codec::parse_f32).unwrap_or_else(|| self.corrupted("buy"));
+
+        Self::apply_recorded_quantity(cash, cash_after);
+        self.rebroadcast("buy");
+        res
+    }
+
+    fn lock_sell(&mut self, kind_to_sell : GoodKind, quantity_to_sell : f32, offer : f32, trader_name : String) -> Result<String, LockSellError> {
+        let args = json!({ "kind": codec::good_kind(kind_to_sell), "quantity": quantity_to_sell, "offer": offer, "trader_name": trader_name });
+        let response = self.next("lock_sell", &args);
+        let res = codec::parse_result(response, |t| t.as_str().map(|t| t.to_string()), codec::parse_lock_sell_error)
+            .unwrap_or_else(|| self.corrupted("lock_sell"));
+        self.rebroadcast("lock_sell");
+        res
+    }
+
+    fn sell(&mut self, token : String, good : &mut Good) -> Result<Good, SellError> {
+        let response = self.next("sell", &json!({ "token": token, "good": codec::good(good) }));
+        let res = response.get("result")
+            .and_then(|r| codec::parse_result(r, codec::parse_good, codec::parse_sell_error))
+            .unwrap_or_else(|| self.corrupted("sell"));
+        let good_after = response.get("good_after").and_then(codec::parse_f32).unwrap_or_else(|| self.corrupted("sell"));
+
+        Self::apply_recorded_quantity(good, good_after);
+        self.rebroadcast("sell");
+        res
+    }
+}
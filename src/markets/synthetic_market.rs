@@ -0,0 +1,313 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use market_common::event::event::{Event, EventKind};
+use market_common::event::notifiable::Notifiable;
+use market_common::good::good::Good;
+use market_common::good::good_kind::GoodKind;
+use market_common::good::good_kind::GoodKind::*;
+use market_common::market::good_label::GoodLabel;
+use market_common::market::{BuyError, LockBuyError, LockSellError, Market, MarketGetterError, SellError};
+
+use crate::markets::lock_book::LockBook;
+
+//no process is allowed to push a price to zero or below
+static MIN_PRICE : f32 = 1e-6;
+static DEFAULT_BUDGET : f32 = 10_000.0;
+static DEFAULT_QUANTITY : f32 = 10_000.0;
+static DEFAULT_SPREAD : f32 = 0.02;
+static DEFAULT_IMPACT : f32 = 0.1;
+
+//All the parameters are per day, since the prices move once per wait().
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriceProcess {
+    //geometric Brownian motion
+    Gbm { drift: f32, volatility: f32 },
+    //Ornstein–Uhlenbeck: every day the price moves `reversion` (0.0..=1.0) of the way back to `mean`, plus noise
+    MeanReverting { mean: f32, reversion: f32, volatility: f32 },
+    //GBM that switches between a calm and a turbulent regime, with random jumps on top
+    RegimeSwitching { calm: Regime, turbulent: Regime, switch_probability: f32, jump_probability: f32, jump_volatility: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Regime {
+    pub drift: f32,
+    pub volatility: f32,
+}
+
+struct SyntheticGood {
+    //EUR per unit, before the spread
+    mid: f32,
+    process: PriceProcess,
+    turbulent: bool,
+}
+
+//A market whose prices follow a stochastic process chosen per good. Everything random comes from the seed,
+//so two markets built the same way with the same seed move the same way.
+//The market quotes mid ± spread/2, and trades move the price against the trader: the average price of a trade
+//grows with the share of the market's inventory it takes, and the mid moves by `impact` times that share afterwards.
+pub struct SyntheticMarket {
+    name: &'static str,
+    seed: u64,
+    rng: StdRng,
+    goods: HashMap<GoodKind, SyntheticGood>,
+    spread: f32,
+    impact: f32,
+    //how far below its ask a bid (and above its bid an offer) the market still accepts, as a fraction of the quote
+    haggle_room: f32,
+    book: LockBook,
+}
+
+//a standard normal sample (Box–Muller)
+fn normal(rng : &mut StdRng) -> f32 {
+    let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+    let u2: f32 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
+
+impl PriceProcess {
+
+    //a driftless GBM with 1% daily volatility
+    pub fn default_gbm() -> Self {
+        PriceProcess::Gbm { drift: 0.0, volatility: 0.01 }
+    }
+
+    fn step(&self, price : f32, turbulent : &mut bool, rng : &mut StdRng) -> f32 {
+        let next = match *self {
+            PriceProcess::Gbm { drift, volatility } => {
+                price * (drift - volatility * volatility / 2.0 + volatility * normal(rng)).exp()
+            }
+            PriceProcess::MeanReverting { mean, reversion, volatility } => {
+                price + reversion * (mean - price) + volatility * mean * normal(rng)
+            }
+            PriceProcess::RegimeSwitching { calm, turbulent: wild, switch_probability, jump_probability, jump_volatility } => {
+                if rng.gen::<f32>() < switch_probability {
+                    *turbulent = !*turbulent;
+                }
+                let regime = if *turbulent { wild } else { calm };
+                let mut next = price * (regime.drift - regime.volatility * regime.volatility / 2.0 + regime.volatility * normal(rng)).exp();
+                if rng.gen::<f32>() < jump_probability {
+                    next *= (jump_volatility * normal(rng)).exp();
+                }
+                next
+            }
+        };
+        next.max(MIN_PRICE)
+    }
+}
+
+impl SyntheticMarket {
+
+    //a market with the default budget and no goods: add them with with_good()
+    pub fn new(seed : u64) -> Self {
+        let mut inventory = HashMap::new();
+        inventory.insert(EUR, DEFAULT_BUDGET);
+        SyntheticMarket {
+            name: "SYNTHETIC",
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            goods: HashMap::new(),
+            spread: DEFAULT_SPREAD,
+            impact: DEFAULT_IMPACT,
+            haggle_room: 0.0,
+            book: LockBook::new("SYNTH", inventory),
+        }
+    }
+
+    //USD, YEN and YUAN at about their real prices, all following default_gbm()
+    pub fn with_default_goods(self, quantity : f32) -> Self {
+        self.with_good(USD, 0.95, quantity, PriceProcess::default_gbm())
+            .with_good(YEN, 0.0068, quantity * 100.0, PriceProcess::default_gbm())
+            .with_good(YUAN, 0.14, quantity * 10.0, PriceProcess::default_gbm())
+    }
+
+    pub fn with_name(mut self, name : &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn with_budget(mut self, budget : f32) -> Self {
+        self.book.set_quantity(EUR, budget);
+        self
+    }
+
+    //`price` is the starting mid price in EUR per unit
+    pub fn with_good(mut self, kind : GoodKind, price : f32, quantity : f32, process : PriceProcess) -> Self {
+        self.goods.insert(kind, SyntheticGood { mid: price.max(MIN_PRICE), process, turbulent: false });
+        self.book.set_quantity(kind, quantity);
+        self
+    }
+
+    //the whole spread, as a fraction of the mid price
+    pub fn with_spread(mut self, spread : f32) -> Self {
+        self.spread = spread.max(0.0);
+        self
+    }
+
+    pub fn with_impact(mut self, impact : f32) -> Self {
+        self.impact = impact.max(0.0);
+        self
+    }
+
+    //by default the market accepts nothing better than its own quotes
+    pub fn with_haggle_room(mut self, room : f32) -> Self {
+        self.haggle_room = room.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn build(self) -> Rc<RefCell<SyntheticMarket>> {
+        Rc::new(RefCell::new(self))
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn day(&self) -> u32 {
+        self.book.day()
+    }
+
+    pub fn mid_price(&self, kind : GoodKind) -> Option<f32> {
+        if kind == EUR {
+            return Some(1.0);
+        }
+        self.goods.get(&kind).map(|g| g.mid)
+    }
+
+    //the share of the market's inventory that a trade of `quantity` represents
+    fn depth_share(&self, kind : GoodKind, quantity : f32) -> f32 {
+        let depth = self.book.available(kind) + quantity;
+        if depth > 0.0 { quantity / depth } else { 1.0 }
+    }
+
+    fn total_ask(&self, kind : GoodKind, quantity : f32) -> Option<f32> {
+        let mid = self.mid_price(kind)?;
+        let slippage = if kind == EUR { 0.0 } else { self.impact * self.depth_share(kind, quantity) / 2.0 };
+        Some(mid * (1.0 + self.spread / 2.0) * (1.0 + slippage) * quantity)
+    }
+
+    fn total_bid(&self, kind : GoodKind, quantity : f32) -> Option<f32> {
+        let mid = self.mid_price(kind)?;
+        let slippage = if kind == EUR { 0.0 } else { self.impact * self.depth_share(kind, quantity) / 2.0 };
+        Some(mid * (1.0 - self.spread / 2.0) * (1.0 - slippage).max(0.0) * quantity)
+    }
+
+    //after a trade the mid stays where the trade pushed it. `share` is the depth_share() of the trade.
+    fn move_mid(&mut self, kind : GoodKind, share : f32, bought_by_trader : bool) {
+        let shift = self.impact * share;
+        if let Some(good) = self.goods.get_mut(&kind) {
+            good.mid = if bought_by_trader { good.mid * (1.0 + shift) } else { (good.mid * (1.0 - shift)).max(MIN_PRICE) };
+        }
+    }
+
+    fn next_day(&mut self) {
+        self.book.advance_day();
+        //always step the goods in the same order, or the seed would not be enough to reproduce a run
+        for kind in [USD, YEN, YUAN] {
+            if let Some(good) = self.goods.get_mut(&kind) {
+                good.mid = good.process.step(good.mid, &mut good.turbulent, &mut self.rng);
+            }
+        }
+    }
+
+    fn not_traded(kind : GoodKind, quantity : f32) -> MarketGetterError {
+        MarketGetterError::InsufficientGoodQuantityAvailable { requested_good_kind: kind, requested_good_quantity: quantity, available_good_quantity: 0.0 }
+    }
+}
+
+impl Notifiable for SyntheticMarket {
+    fn add_subscriber(&mut self, subscriber : Box<dyn Notifiable>) {
+        self.book.add_subscriber(subscriber);
+    }
+
+    //the other markets' trades don't move these prices: only the days do
+    fn on_event(&mut self, event : Event) {
+        if let EventKind::Wait = event.kind {
+            self.next_day();
+        }
+    }
+}
+
+impl Market for SyntheticMarket {
+    fn new_random() -> Rc<RefCell<dyn Market>> where Self: Sized {
+        SyntheticMarket::new(rand::random()).with_default_goods(DEFAULT_QUANTITY).build()
+    }
+
+    //always the same seed: the quantities are the only thing that changes
+    fn new_with_quantities(eur : f32, yen : f32, usd : f32, yuan : f32) -> Rc<RefCell<dyn Market>> where Self: Sized {
+        SyntheticMarket::new(0)
+            .with_budget(eur)
+            .with_good(USD, 0.95, usd, PriceProcess::default_gbm())
+            .with_good(YEN, 0.0068, yen, PriceProcess::default_gbm())
+            .with_good(YUAN, 0.14, yuan, PriceProcess::default_gbm())
+            .build()
+    }
+
+    fn new_file(_ : &str) -> Rc<RefCell<dyn Market>> where Self: Sized {
+        panic!("A SyntheticMarket is configured in code: use SyntheticMarket::new(seed)");
+    }
+
+    fn get_name(&self) -> &'static str {
+        self.name
+    }
+
+    fn get_budget(&self) -> f32 {
+        self.book.budget()
+    }
+
+    fn get_buy_price(&self, kind : GoodKind, quantity : f32) -> Result<f32, MarketGetterError> {
+        self.book.check_quote(kind, quantity)?;
+        self.total_ask(kind, quantity).ok_or_else(|| Self::not_traded(kind, quantity))
+    }
+
+    fn get_sell_price(&self, kind : GoodKind, quantity : f32) -> Result<f32, MarketGetterError> {
+        if quantity <= 0.0 {
+            return Err(MarketGetterError::NonPositiveQuantityAsked);
+        }
+        self.total_bid(kind, quantity).ok_or_else(|| Self::not_traded(kind, quantity))
+    }
+
+    fn get_goods(&self) -> Vec<GoodLabel> {
+        self.book.goods(|kind| (self.total_ask(kind, 1.0).unwrap_or(0.0), self.total_bid(kind, 1.0).unwrap_or(0.0)))
+    }
+
+    fn lock_buy(&mut self, kind_to_buy : GoodKind, quantity_to_buy : f32, bid : f32, _trader_name : String) -> Result<String, LockBuyError> {
+        let lowest_acceptable_bid = match self.total_ask(kind_to_buy, quantity_to_buy) {
+            Some(price) => price * (1.0 - self.haggle_room),
+            None => return Err(LockBuyError::InsufficientGoodQuantityAvailable {
+                requested_good_kind: kind_to_buy,
+                requested_good_quantity: quantity_to_buy,
+                available_good_quantity: 0.0,
+            }),
+        };
+        self.book.lock_buy(kind_to_buy, quantity_to_buy, bid, lowest_acceptable_bid)
+    }
+
+    fn buy(&mut self, token : String, cash : &mut Good) -> Result<Good, BuyError> {
+        //the share is taken before the trade changes the inventory
+        let moved = self.book.lock(&token).map(|l| (l.kind, self.depth_share(l.kind, l.quantity)));
+        let bought = self.book.buy(token, cash)?;
+        if let Some((kind, share)) = moved {
+            self.move_mid(kind, share, true);
+        }
+        Ok(bought)
+    }
+
+    fn lock_sell(&mut self, kind_to_sell : GoodKind, quantity_to_sell : f32, offer : f32, _trader_name : String) -> Result<String, LockSellError> {
+        let highest_acceptable_offer = self.total_bid(kind_to_sell, quantity_to_sell).unwrap_or(0.0) * (1.0 + self.haggle_room);
+        self.book.lock_sell(kind_to_sell, quantity_to_sell, offer, highest_acceptable_offer)
+    }
+
+    fn sell(&mut self, token : String, good : &mut Good) -> Result<Good, SellError> {
+        let moved = self.book.lock(&token).map(|l| (l.kind, self.depth_share(l.kind, l.quantity)));
+        let proceeds = self.book.sell(token, good)?;
+        if let Some((kind, share)) = moved {
+            self.move_mid(kind, share, false);
+        }
+        Ok(proceeds)
+    }
+}
@@ -0,0 +1,143 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use market_common::event::event::Event;
+use market_common::event::notifiable::Notifiable;
+use market_common::good::good::Good;
+use market_common::good::good_kind::GoodKind;
+use market_common::market::good_label::GoodLabel;
+use market_common::market::{BuyError, LockBuyError, LockSellError, Market, MarketGetterError, SellError};
+
+use crate::trader::trader_sync::MutexMarket;
+
+type Job = Box<dyn FnOnce(&[Rc<RefCell<dyn Market>>]) + Send>;
+
+//A market living on a thread of its own. The bose/bfb/tase markets (and the ones in this crate) are Rc<RefCell<dyn Market>>,
+//which can't leave the thread that built them: spawn() builds them on a new thread, and every call is sent there and waited for.
+//The handle is Send + Sync, so it can go in a MutexMarket (see shared()) and be used by a SyncTrader.
+pub struct ThreadedMarket {
+    jobs: Sender<Job>,
+    index: usize,
+    //get_name() has to hand out a &'static str: it's asked once, when the market is built
+    name: &'static str,
+}
+
+impl ThreadedMarket {
+
+    pub fn spawn(build : impl FnOnce() -> Rc<RefCell<dyn Market>> + Send + 'static) -> Self {
+        Self::spawn_group(move || vec![build()]).into_iter().next()
+            .expect("The market thread didn't return the market it built. Panic!")
+    }
+
+    //Builds several markets on the same thread, so they can subscribe to each other there (e.g. with subscribe_each_other!).
+    //The handles come back in the same order as the markets. The thread stops once all of them are dropped.
+    pub fn spawn_group(build : impl FnOnce() -> Vec<Rc<RefCell<dyn Market>>> + Send + 'static) -> Vec<Self> {
+        let (jobs, inbox) = mpsc::channel::<Job>();
+        let (names_sender, names) = mpsc::channel();
+        thread::spawn(move || {
+            let markets = build();
+            let market_names: Vec<&'static str> = markets.iter().map(|m| m.borrow().get_name()).collect();
+            if names_sender.send(market_names).is_err() {
+                return;
+            }
+            for job in inbox {
+                job(&markets);
+            }
+        });
+
+        let names = names.recv().expect("The market thread panicked while building its markets. Panic!");
+        names.into_iter().enumerate()
+            .map(|(index, name)| ThreadedMarket { jobs: jobs.clone(), index, name })
+            .collect()
+    }
+
+    pub fn shared(self) -> MutexMarket {
+        Arc::new(Mutex::new(self))
+    }
+
+    //runs `f` on the market's thread and waits for the result. If the market panics, so does the caller.
+    fn call<R : Send + 'static>(&self, f : impl FnOnce(&mut dyn Market) -> R + Send + 'static) -> R {
+        let (reply, result) = mpsc::channel();
+        let index = self.index;
+        let job: Job = Box::new(move |markets| {
+            let value = f(&mut *markets[index].borrow_mut());
+            let _ = reply.send(value);
+        });
+        self.jobs.send(job).expect("The market thread is gone. Panic!");
+        result.recv().expect("The market panicked on its thread. Panic!")
+    }
+}
+
+impl Notifiable for ThreadedMarket {
+    //The subscriber would have to cross threads, and it can't: it's dropped, so this market tells nobody outside
+    //its thread about its trades. The markets that should hear them are subscribed inside ThreadedMarket::spawn_group().
+    //It still hears the others through on_event(), so attaching it to a Trader next to other markets works.
+    fn add_subscriber(&mut self, _subscriber : Box<dyn Notifiable>) {}
+
+    fn on_event(&mut self, event : Event) {
+        self.call(move |m| m.on_event(event));
+    }
+}
+
+//The constructors can't know which market to build: use spawn() or spawn_group()
+impl Market for ThreadedMarket {
+    fn new_random() -> Rc<RefCell<dyn Market>> where Self: Sized {
+        panic!("A ThreadedMarket runs a market built elsewhere: use ThreadedMarket::spawn()");
+    }
+
+    fn new_with_quantities(_ : f32, _ : f32, _ : f32, _ : f32) -> Rc<RefCell<dyn Market>> where Self: Sized {
+        panic!("A ThreadedMarket runs a market built elsewhere: use ThreadedMarket::spawn()");
+    }
+
+    fn new_file(_ : &str) -> Rc<RefCell<dyn Market>> where Self: Sized {
+        panic!("A ThreadedMarket runs a market built elsewhere: use ThreadedMarket::spawn()");
+    }
+
+    fn get_name(&self) -> &'static str {
+        self.name
+    }
+
+    fn get_budget(&self) -> f32 {
+        self.call(|m| m.get_budget())
+    }
+
+    fn get_buy_price(&self, kind : GoodKind, quantity : f32) -> Result<f32, MarketGetterError> {
+        self.call(move |m| m.get_buy_price(kind, quantity))
+    }
+
+    fn get_sell_price(&self, kind : GoodKind, quantity : f32) -> Result<f32, MarketGetterError> {
+        self.call(move |m| m.get_sell_price(kind, quantity))
+    }
+
+    fn get_goods(&self) -> Vec<GoodLabel> {
+        self.call(|m| m.get_goods())
+    }
+
+    fn lock_buy(&mut self, kind_to_buy : GoodKind, quantity_to_buy : f32, bid : f32, trader_name : String) -> Result<String, LockBuyError> {
+        self.call(move |m| m.lock_buy(kind_to_buy, quantity_to_buy, bid, trader_name))
+    }
+
+    //the cash travels to the market's thread and back with whatever the market left in it
+    fn buy(&mut self, token : String, cash : &mut Good) -> Result<Good, BuyError> {
+        let kind = cash.get_kind();
+        let mut sent = std::mem::replace(cash, Good::new(kind, 0.0));
+        let (result, left) = self.call(move |m| (m.buy(token, &mut sent), sent));
+        *cash = left;
+        result
+    }
+
+    fn lock_sell(&mut self, kind_to_sell : GoodKind, quantity_to_sell : f32, offer : f32, trader_name : String) -> Result<String, LockSellError> {
+        self.call(move |m| m.lock_sell(kind_to_sell, quantity_to_sell, offer, trader_name))
+    }
+
+    fn sell(&mut self, token : String, good : &mut Good) -> Result<Good, SellError> {
+        let kind = good.get_kind();
+        let mut sent = std::mem::replace(good, Good::new(kind, 0.0));
+        let (result, left) = self.call(move |m| (m.sell(token, &mut sent), sent));
+        *good = left;
+        result
+    }
+}
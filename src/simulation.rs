@@ -0,0 +1,192 @@
+pub mod monte_carlo;
+pub mod tournament;
+pub mod optimizer;
+pub mod environment;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use bfb::bfb_market::Bfb;
+use bose::market::BoseMarket;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+
+use market_common::good::good_kind::GoodKind;
+use market_common::market::Market;
+
+use crate::markets::recording_market::RecordingMarket;
+use crate::markets::synthetic_market::SyntheticMarket;
+use crate::trader::{MarketKind, Trader, DEFAULT_OUTPUT_FILE};
+
+//the range the starting inventories of the real markets are drawn from
+static MIN_MARKET_EUR : f32 = 5_000.0;
+static MAX_MARKET_EUR : f32 = 50_000.0;
+static MIN_MARKET_GOOD_EUR : f32 = 5_000.0;
+static MAX_MARKET_GOOD_EUR : f32 = 50_000.0;
+//rough EUR value of a unit, so that the inventories of the different goods are comparable
+static ROUGH_UNIT_VALUE : [(GoodKind, f32); 3] = [(GoodKind::USD, 0.95), (GoodKind::YEN, 0.0068), (GoodKind::YUAN, 0.14)];
+static SYNTHETIC_GOOD_QUANTITY : f32 = 10_000.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarketSetup {
+    //the other groups' market of that kind. They can't be seeded, so they are built with new_with_quantities()
+    //and inventories derived from the seed: their starting state is reproducible, their own randomness is not.
+    Real,
+    //a SyntheticMarket with default goods and a seed derived from the master seed: fully reproducible
+    Synthetic,
+    //a market loaded from a state file, the same in every simulation
+    FromFile(String),
+}
+
+//Builds the markets and the trader of a simulation from one master seed.
+//Every market gets its own seed, derived from the master seed and its kind, so adding a market doesn't change the others.
+pub struct SimulationBuilder {
+    seed: u64,
+    markets: Vec<(MarketKind, MarketSetup)>,
+    amazingness: f32,
+    initial_money: Option<f32>,
+    goods: Vec<(GoodKind, f32)>,
+    output_file: Option<String>,
+    recording_dir: Option<PathBuf>,
+}
+
+//SplitMix64: a cheap, well mixed and (unlike the rand generators) version-independent way to derive seeds
+pub(crate) fn split_mix(mut x : u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+pub fn derive_seed(master : u64, kind : MarketKind) -> u64 {
+    split_mix(master ^ split_mix(kind as u64 + 1))
+}
+
+//the reports' CSV exports: a header line, then one line per row
+pub(crate) fn write_csv(path : impl AsRef<Path>, header : Vec<String>, rows : Vec<Vec<String>>) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "{}", header.join(","))?;
+    for row in rows {
+        writeln!(file, "{}", row.join(","))?;
+    }
+    Ok(())
+}
+
+impl SimulationBuilder {
+
+    pub fn new(seed : u64) -> Self {
+        SimulationBuilder {
+            seed,
+            markets: Vec::new(),
+            amazingness: 1.0,
+            initial_money: None,
+            goods: Vec::new(),
+            output_file: Some(DEFAULT_OUTPUT_FILE.to_string()),
+            recording_dir: None,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn with_market(mut self, kind : MarketKind, setup : MarketSetup) -> Self {
+        self.markets.retain(|(k, _)| *k != kind);
+        self.markets.push((kind, setup));
+        self
+    }
+
+    pub fn with_amazingness(mut self, amazingness : f32) -> Self {
+        self.amazingness = amazingness;
+        self
+    }
+
+    pub fn with_initial_money(mut self, money : f32) -> Self {
+        self.initial_money = Some(money);
+        self
+    }
+
+    pub fn with_good(mut self, kind : GoodKind, quantity : f32) -> Self {
+        self.goods.push((kind, quantity));
+        self
+    }
+
+    pub fn with_output_file(mut self, path : &str) -> Self {
+        self.output_file = Some(path.to_string());
+        self
+    }
+
+    pub fn without_output_file(mut self) -> Self {
+        self.output_file = None;
+        self
+    }
+
+    //records every market to `<dir>/<kind>.jsonl`, with the seeds in the header
+    pub fn with_recording(mut self, dir : impl Into<PathBuf>) -> Self {
+        self.recording_dir = Some(dir.into());
+        self
+    }
+
+    pub fn market_seed(&self, kind : MarketKind) -> u64 {
+        derive_seed(self.seed, kind)
+    }
+
+    //the starting inventories of a real market: (eur, yen, usd, yuan), in the order of new_with_quantities()
+    pub fn market_quantities(&self, kind : MarketKind) -> (f32, f32, f32, f32) {
+        let mut rng = StdRng::seed_from_u64(self.market_seed(kind));
+        let eur = rng.gen_range(MIN_MARKET_EUR..MAX_MARKET_EUR);
+        let mut goods: HashMap<GoodKind, f32> = HashMap::new();
+        for (good, unit_value) in ROUGH_UNIT_VALUE.iter() {
+            goods.insert(*good, rng.gen_range(MIN_MARKET_GOOD_EUR..MAX_MARKET_GOOD_EUR) / unit_value);
+        }
+        (eur, goods[&GoodKind::YEN], goods[&GoodKind::USD], goods[&GoodKind::YUAN])
+    }
+
+    fn build_market(&self, kind : MarketKind, setup : &MarketSetup) -> Rc<RefCell<dyn Market>> {
+        let (eur, yen, usd, yuan) = self.market_quantities(kind);
+        match (setup, kind) {
+            (MarketSetup::FromFile(path), MarketKind::BOSE) => BoseMarket::new_file(path),
+            (MarketSetup::FromFile(path), MarketKind::BFB) => Bfb::new_file(path),
+            (MarketSetup::FromFile(path), MarketKind::TASE) => tase::TASE::new_file(path),
+            (MarketSetup::Real, MarketKind::BOSE) => BoseMarket::new_with_quantities(eur, yen, usd, yuan),
+            (MarketSetup::Real, MarketKind::BFB) => Bfb::new_with_quantities(eur, yen, usd, yuan),
+            (MarketSetup::Real, MarketKind::TASE) => tase::TASE::new_with_quantities(eur, yen, usd, yuan),
+            //we don't depend on a DOGE implementation (and PANIC is not a market): a synthetic market takes their place
+            _ => SyntheticMarket::new(self.market_seed(kind))
+                .with_name(if kind == MarketKind::DOGE { "DOGE" } else { "SYNTHETIC" })
+                .with_budget(eur)
+                .with_default_goods(SYNTHETIC_GOOD_QUANTITY)
+                .build(),
+        }
+    }
+
+    pub fn build(self) -> io::Result<Trader> {
+        let mut trader = Trader::new_super_duper_amazing_trader(self.amazingness).with_simulation_seed(self.seed);
+        trader = match &self.output_file {
+            Some(path) => trader.with_output_file(path),
+            None => trader.without_output_file(),
+        };
+        if let Some(money) = self.initial_money {
+            trader = trader.with_initial_money(money);
+        }
+        for (kind, quantity) in self.goods.iter() {
+            trader = trader.with_good(*kind, *quantity);
+        }
+
+        for (kind, setup) in self.markets.iter() {
+            let mut market = self.build_market(*kind, setup);
+            if let Some(dir) = &self.recording_dir {
+                let header = json!({ "seed": self.seed, "market_seed": self.market_seed(*kind), "setup": format!("{:?}", setup) });
+                market = RecordingMarket::wrap_with_header(market, dir.join(format!("{:?}.jsonl", kind)), header)?;
+            }
+            trader = trader.with_market(*kind, market);
+        }
+        Ok(trader)
+    }
+}
@@ -0,0 +1,280 @@
+use std::fs::File;
+use std::io;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use market_common::good::good_kind::GoodKind;
+use market_common::good::good_kind::GoodKind::*;
+
+use crate::markets::market_codec as codec;
+use crate::simulation::SimulationBuilder;
+use crate::trader::{MarketKind, Trader};
+
+//the goods the agent can trade, in the order used by the observations and the action space
+static TRADED_GOODS : [GoodKind; 3] = [USD, YEN, YUAN];
+static HELD_GOODS : [GoodKind; 4] = [EUR, USD, YEN, YUAN];
+//the quantity used to read a unit price, the same one the visualizer recorder uses
+static PRICE_PROBE_QTY : f32 = 0.01;
+static MIN_TRADE_QTY : f32 = 0.01;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Hold,
+    //spend `fraction` (0.0..=1.0) of the euros on `kind`
+    Buy { market: MarketKind, kind: GoodKind, fraction: f32 },
+    //sell `fraction` (0.0..=1.0) of the `kind` the trader owns
+    Sell { market: MarketKind, kind: GoodKind, fraction: f32 },
+    //one value in -1.0..=1.0 per (market, good) pair, in ActionSpace::pairs() order:
+    //a positive value buys with that fraction of the euros, a negative one sells that fraction of the holdings
+    Continuous(Vec<f32>),
+}
+
+//Discrete actions: 0 is Hold, then a Buy and a Sell of `trade_fraction` for every (market, good) pair.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionSpace {
+    pub markets: Vec<MarketKind>,
+    pub trade_fraction: f32,
+}
+
+impl ActionSpace {
+
+    pub fn pairs(&self) -> Vec<(MarketKind, GoodKind)> {
+        self.markets.iter().flat_map(|m| TRADED_GOODS.iter().map(move |g| (*m, *g))).collect()
+    }
+
+    pub fn discrete_len(&self) -> usize {
+        1 + 2 * self.pairs().len()
+    }
+
+    //the size of the vector of an Action::Continuous
+    pub fn continuous_len(&self) -> usize {
+        self.pairs().len()
+    }
+
+    pub fn action(&self, index : usize) -> Option<Action> {
+        if index == 0 {
+            return Some(Action::Hold);
+        }
+        let (market, kind) = *self.pairs().get((index - 1) / 2)?;
+        Some(if (index - 1) % 2 == 0 {
+            Action::Buy { market, kind, fraction: self.trade_fraction }
+        } else {
+            Action::Sell { market, kind, fraction: self.trade_fraction }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepResult {
+    pub observation: Vec<f32>,
+    //change in capital during the step
+    pub reward: f32,
+    pub done: bool,
+    //the trades of the action that the trader or the markets refused
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub observation: Vec<f32>,
+    pub action: Action,
+    pub reward: f32,
+    pub next_observation: Vec<f32>,
+    pub done: bool,
+}
+
+//A gym-style wrapper around a trader and its markets. Every step applies an action and waits one day.
+//The world is rebuilt from its seed at every reset(), so an episode can be played again.
+//
+//The observation is, in this order:
+// - for every market and traded good: unit buy price, unit sell price, market quantity
+// - the trader's holdings of EUR, USD, YEN, YUAN
+// - the EUR budget of every market
+pub struct TradingEnvironment {
+    world: Box<dyn Fn(u64) -> SimulationBuilder>,
+    markets: Vec<MarketKind>,
+    max_days: u32,
+    trade_fraction: f32,
+    trader: Option<Trader>,
+    seed: u64,
+    day: u32,
+    //the last step returned done: the episode is over until the next reset()
+    finished: bool,
+    observation: Vec<f32>,
+    trajectory: Vec<Transition>,
+}
+
+impl TradingEnvironment {
+
+    //`markets` must be the markets the world builds: they define the observation and the action space
+    pub fn new(world : impl Fn(u64) -> SimulationBuilder + 'static, markets : Vec<MarketKind>) -> Self {
+        TradingEnvironment {
+            world: Box::new(world),
+            markets,
+            max_days: 100,
+            trade_fraction: 0.1,
+            trader: None,
+            seed: 0,
+            day: 0,
+            finished: false,
+            observation: Vec::new(),
+            trajectory: Vec::new(),
+        }
+    }
+
+    pub fn with_max_days(mut self, days : u32) -> Self {
+        self.max_days = days;
+        self
+    }
+
+    pub fn with_trade_fraction(mut self, fraction : f32) -> Self {
+        self.trade_fraction = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn action_space(&self) -> ActionSpace {
+        ActionSpace { markets: self.markets.clone(), trade_fraction: self.trade_fraction }
+    }
+
+    pub fn observation_labels(&self) -> Vec<String> {
+        let mut labels = Vec::new();
+        for market in self.markets.iter() {
+            for kind in TRADED_GOODS.iter() {
+                labels.push(format!("{:?}.{}.buy_price", market, kind));
+                labels.push(format!("{:?}.{}.sell_price", market, kind));
+                labels.push(format!("{:?}.{}.liquidity", market, kind));
+            }
+        }
+        labels.extend(HELD_GOODS.iter().map(|kind| format!("trader.{}", kind)));
+        labels.extend(self.markets.iter().map(|market| format!("{:?}.budget", market)));
+        labels
+    }
+
+    pub fn trader(&self) -> Option<&Trader> {
+        self.trader.as_ref()
+    }
+
+    pub fn trajectory(&self) -> &[Transition] {
+        &self.trajectory
+    }
+
+    pub fn reset(&mut self, seed : u64) -> io::Result<Vec<f32>> {
+        self.trader = Some((self.world)(seed).without_output_file().build()?);
+        self.seed = seed;
+        self.day = 0;
+        self.finished = false;
+        self.trajectory.clear();
+        self.observation = self.observe();
+        Ok(self.observation.clone())
+    }
+
+    fn observe(&self) -> Vec<f32> {
+        let trader = match &self.trader {
+            Some(trader) => trader,
+            None => return Vec::new(),
+        };
+        let unit = |price : Option<f32>| price.map_or(0.0, |p| p / PRICE_PROBE_QTY);
+
+        let mut observation = Vec::new();
+        for market in self.markets.iter() {
+            for kind in TRADED_GOODS.iter() {
+                observation.push(unit(trader.try_supply_price_qt(*market, *kind, PRICE_PROBE_QTY)));
+                observation.push(unit(trader.try_demand_price_qt(*market, *kind, PRICE_PROBE_QTY)));
+                observation.push(trader.get_good_qty(*market, *kind));
+            }
+        }
+        observation.extend(HELD_GOODS.iter().map(|kind| trader.get_owned_good_qty(*kind)));
+        observation.extend(self.markets.iter().map(|market| trader.get_market(*market).map_or(0.0, |m| m.borrow().get_budget())));
+        observation
+    }
+
+    fn trade(trader : &mut Trader, market : MarketKind, kind : GoodKind, fraction : f32, errors : &mut Vec<String>) {
+        let fraction = fraction.clamp(-1.0, 1.0);
+        if fraction > 0.0 {
+            let budget = trader.get_owned_good_qty(EUR) * fraction;
+            if let Err(e) = trader.buy_with_budget(market, kind, budget) {
+                errors.push(format!("buy {} on {:?}: {:?}", kind, market, e));
+            }
+        } else if fraction < 0.0 {
+            let quantity = trader.get_owned_good_qty(kind) * -fraction;
+            if quantity < MIN_TRADE_QTY {
+                return;
+            }
+            if let Err(e) = trader.sell(market, kind, quantity) {
+                errors.push(format!("sell {} on {:?}: {:?}", kind, market, e));
+            }
+        }
+    }
+
+    //panics if reset() was never called, or if the episode is over (a step returned done) and wasn't reset()
+    pub fn step(&mut self, action : Action) -> StepResult {
+        assert!(!self.finished, "step() called after the episode was done: call reset() first");
+        let pairs = self.action_space().pairs();
+        let trader = self.trader.as_mut().expect("step() called before reset()");
+        let capital_before = trader.get_capital();
+        let mut errors = Vec::new();
+
+        match &action {
+            Action::Hold => {}
+            Action::Buy { market, kind, fraction } => Self::trade(trader, *market, *kind, fraction.abs(), &mut errors),
+            Action::Sell { market, kind, fraction } => Self::trade(trader, *market, *kind, -fraction.abs(), &mut errors),
+            Action::Continuous(values) => {
+                for ((market, kind), value) in pairs.iter().zip(values.iter()) {
+                    Self::trade(trader, *market, *kind, *value, &mut errors);
+                }
+            }
+        }
+
+        trader.wait();
+        self.day += 1;
+        let reward = trader.get_capital() - capital_before;
+        let done = self.day >= self.max_days || trader.get_capital() <= 0.0;
+        self.finished = done;
+
+        let observation = self.observe();
+        self.trajectory.push(Transition {
+            observation: std::mem::replace(&mut self.observation, observation.clone()),
+            action,
+            reward,
+            next_observation: observation.clone(),
+            done,
+        });
+
+        StepResult { observation, reward, done, errors }
+    }
+
+    //the episode so far, as JSON lines: a header with the seed and the labels, then one line per transition
+    pub fn export_trajectory(&self, path : impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = LineWriter::new(File::create(path)?);
+        let header = json!({ "header": {
+            "seed": self.seed,
+            "markets": self.markets.iter().map(|m| format!("{:?}", m)).collect::<Vec<_>>(),
+            "observation_labels": self.observation_labels(),
+        }});
+        writeln!(writer, "{}", header)?;
+
+        for (step, transition) in self.trajectory.iter().enumerate() {
+            let line = json!({
+                "step": step,
+                "observation": transition.observation,
+                "action": action_json(&transition.action),
+                "reward": transition.reward,
+                "next_observation": transition.next_observation,
+                "done": transition.done,
+            });
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+fn action_json(action : &Action) -> Value {
+    match action {
+        Action::Hold => json!({ "type": "hold" }),
+        Action::Buy { market, kind, fraction } => json!({ "type": "buy", "market": format!("{:?}", market), "good": codec::good_kind(*kind), "fraction": fraction }),
+        Action::Sell { market, kind, fraction } => json!({ "type": "sell", "market": format!("{:?}", market), "good": codec::good_kind(*kind), "fraction": fraction }),
+        Action::Continuous(values) => json!({ "type": "continuous", "values": values }),
+    }
+}
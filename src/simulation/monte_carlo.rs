@@ -0,0 +1,207 @@
+use std::io;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::simulation::{split_mix, write_csv, SimulationBuilder};
+use crate::trader::Trader;
+use crate::trader::trader_run::{panic_message, RunResult, StopCondition, StopReason};
+use crate::trader::trader_sync::run_in_parallel;
+
+//Summary statistics of a sample. The percentiles are interpolated between the closest samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution {
+    pub count: usize,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub std_dev: f32,
+    pub p5: f32,
+    pub p25: f32,
+    pub median: f32,
+    pub p75: f32,
+    pub p95: f32,
+}
+
+//`sorted` must be sorted and not empty; `p` goes from 0.0 to 100.0
+pub fn percentile(sorted : &[f32], p : f32) -> f32 {
+    let rank = (p / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f32;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f32)
+}
+
+impl Distribution {
+
+    //None for an empty sample. NaNs are left out.
+    pub fn from_samples(samples : &[f32]) -> Option<Self> {
+        let mut sorted: Vec<f32> = samples.iter().copied().filter(|s| !s.is_nan()).collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let count = sorted.len();
+        let mean = sorted.iter().sum::<f32>() / count as f32;
+        //sample standard deviation: 0.0 for a single run
+        let std_dev = if count > 1 {
+            (sorted.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / (count - 1) as f32).sqrt()
+        } else {
+            0.0
+        };
+
+        Some(Distribution {
+            count,
+            min: sorted[0],
+            max: sorted[count - 1],
+            mean,
+            std_dev,
+            p5: percentile(&sorted, 5.0),
+            p25: percentile(&sorted, 25.0),
+            median: percentile(&sorted, 50.0),
+            p75: percentile(&sorted, 75.0),
+            p95: percentile(&sorted, 95.0),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeedRun {
+    pub seed: u64,
+    //Err if the simulation couldn't even be built
+    pub result: Result<RunResult, String>,
+}
+
+impl SeedRun {
+
+    //the run didn't get to its end: the world couldn't be built or the strategy panicked
+    pub fn is_failed(&self) -> bool {
+        match &self.result {
+            Ok(result) => matches!(result.reason, StopReason::StrategyPanicked(_)),
+            Err(_) => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonteCarloReport {
+    pub runs: Vec<SeedRun>,
+    //the distributions only count the runs that were built, failed ones included: a panic is part of the strategy's record
+    pub final_capital: Option<Distribution>,
+    pub pnl: Option<Distribution>,
+    pub max_drawdown: Option<Distribution>,
+    pub failed_runs: usize,
+    //the panics contained by the run loop, across all the runs
+    pub strategy_failures: usize,
+}
+
+impl MonteCarloReport {
+
+    fn from_runs(runs : Vec<SeedRun>) -> Self {
+        let results: Vec<&RunResult> = runs.iter().filter_map(|r| r.result.as_ref().ok()).collect();
+        let final_capital: Vec<f32> = results.iter().map(|r| r.final_capital).collect();
+        let pnl: Vec<f32> = results.iter().map(|r| r.pnl()).collect();
+        let drawdown: Vec<f32> = results.iter().map(|r| r.max_drawdown()).collect();
+
+        MonteCarloReport {
+            final_capital: Distribution::from_samples(&final_capital),
+            pnl: Distribution::from_samples(&pnl),
+            max_drawdown: Distribution::from_samples(&drawdown),
+            failed_runs: runs.iter().filter(|r| r.is_failed()).count(),
+            strategy_failures: results.iter().map(|r| r.failures.len()).sum(),
+            runs,
+        }
+    }
+
+    //the share of the built runs that ended with more capital than they started with
+    pub fn profitable_share(&self) -> f32 {
+        let results: Vec<&RunResult> = self.runs.iter().filter_map(|r| r.result.as_ref().ok()).collect();
+        if results.is_empty() {
+            return 0.0;
+        }
+        results.iter().filter(|r| r.pnl() > 0.0).count() as f32 / results.len() as f32
+    }
+
+    //one line per run, with its seed: any of them can be built again with the same world function
+    pub fn write_csv(&self, path : impl AsRef<Path>) -> io::Result<()> {
+        let header: Vec<String> = ["seed", "final_capital", "pnl", "max_drawdown", "failed", "stop_reason"].iter().map(|c| c.to_string()).collect();
+        let rows: Vec<Vec<String>> = self.runs.iter().map(|run| {
+            let (capital, pnl, drawdown, reason) = match &run.result {
+                Ok(result) => (result.final_capital.to_string(), result.pnl().to_string(), result.max_drawdown().to_string(), format!("{:?}", result.reason)),
+                Err(e) => (String::new(), String::new(), String::new(), e.clone()),
+            };
+            //the reason is free text: keep the commas out of the columns
+            vec![run.seed.to_string(), capital, pnl, drawdown, run.is_failed().to_string(), reason.replace(',', ";")]
+        }).collect();
+        write_csv(path, header, rows)
+    }
+}
+
+//Unless told otherwise, a batch run lasts a simulated month. The iteration cap stops the strategies that never wait().
+pub(crate) fn default_batch_stop() -> StopCondition {
+    StopCondition::Any(vec![StopCondition::Days(30), StopCondition::MaxIterations(1_000)])
+}
+
+//Runs the same strategy in many worlds, one per seed. Each world is built by the `world` function from its seed,
+//inside one of the run_in_parallel() workers (no more threads than the machine can run at once).
+//The traders don't write any output file: with hundreds of runs they would overwrite each other.
+pub struct MonteCarlo {
+    seeds: Vec<u64>,
+    world: Box<dyn Fn(u64) -> SimulationBuilder + Sync>,
+    strategy: Arc<dyn Fn(&mut Trader) + Send + Sync>,
+    stop: StopCondition,
+}
+
+//`runs` seeds derived from `master_seed`
+pub fn derive_seeds(master_seed : u64, runs : u32) -> Vec<u64> {
+    (0..runs as u64).map(|i| split_mix(master_seed.wrapping_add(i))).collect()
+}
+
+impl MonteCarlo {
+
+    pub fn new(world : impl Fn(u64) -> SimulationBuilder + Sync + 'static, strategy : impl Fn(&mut Trader) + Send + Sync + 'static) -> Self {
+        MonteCarlo {
+            seeds: derive_seeds(0, 100),
+            world: Box::new(world),
+            strategy: Arc::new(strategy),
+            stop: default_batch_stop(),
+        }
+    }
+
+    pub fn with_runs(mut self, master_seed : u64, runs : u32) -> Self {
+        self.seeds = derive_seeds(master_seed, runs);
+        self
+    }
+
+    pub fn with_seeds(mut self, seeds : Vec<u64>) -> Self {
+        self.seeds = seeds;
+        self
+    }
+
+    pub fn with_stop_condition(mut self, stop : StopCondition) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    pub fn seeds(&self) -> &[u64] {
+        &self.seeds
+    }
+
+    pub fn run(&self) -> MonteCarloReport {
+        let runs = run_in_parallel(&self.seeds, |seed| {
+            let strategy = Arc::clone(&self.strategy);
+            SeedRun { seed, result: run_seed(&*self.world, seed, move |trader : &mut Trader| strategy(trader), &self.stop) }
+        });
+        MonteCarloReport::from_runs(runs)
+    }
+}
+
+//One run of a batch: builds the world of `seed` (without output file), gives the trader the strategy and runs it.
+//Err if the world couldn't be built, panics included: one broken world doesn't stop the batch.
+//Shared by the Monte Carlo runs, the tournaments and the optimizer.
+pub(crate) fn run_seed(world : &(dyn Fn(u64) -> SimulationBuilder + Sync), seed : u64, strategy : impl Fn(&mut Trader) + 'static, stop : &StopCondition) -> Result<RunResult, String> {
+    let built = catch_unwind(AssertUnwindSafe(|| world(seed).without_output_file().build()))
+        .map_err(|payload| format!("building the world panicked: {}", panic_message(&*payload)))?;
+    let mut trader = built.map_err(|e| e.to_string())?;
+    trader.set_strategy(strategy);
+    Ok(trader.run_until(stop.clone()))
+}
@@ -0,0 +1,329 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::simulation::monte_carlo::{default_batch_stop, derive_seeds, run_seed};
+use crate::simulation::{write_csv, SimulationBuilder};
+use crate::trader::Trader;
+use crate::trader::trader_run::{RunResult, StopCondition, StopReason};
+use crate::trader::trader_sync::run_in_parallel;
+
+//the value of every parameter, by name. A BTreeMap so that the columns of the CSV are always in the same order.
+pub type Params = BTreeMap<String, f32>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamRange {
+    Values(Vec<f32>),
+    //`steps` evenly spaced values from min to max (both included) for a grid search; any value in between for a random one
+    Range { min: f32, max: f32, steps: u32 },
+}
+
+impl ParamRange {
+
+    fn grid_values(&self) -> Vec<f32> {
+        match self {
+            ParamRange::Values(values) => values.clone(),
+            ParamRange::Range { min, max, steps } => match steps {
+                0 => Vec::new(),
+                1 => vec![*min],
+                _ => (0..*steps).map(|i| min + (max - min) * i as f32 / (*steps - 1) as f32).collect(),
+            },
+        }
+    }
+
+    fn sample(&self, rng : &mut StdRng) -> Option<f32> {
+        match self {
+            ParamRange::Values(values) if values.is_empty() => None,
+            ParamRange::Values(values) => Some(values[rng.gen_range(0..values.len())]),
+            ParamRange::Range { min, max, .. } if min >= max => Some(*min),
+            ParamRange::Range { min, max, .. } => Some(rng.gen_range(*min..*max)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParameterSpace {
+    params: Vec<(String, ParamRange)>,
+}
+
+impl ParameterSpace {
+
+    pub fn new() -> Self {
+        ParameterSpace::default()
+    }
+
+    pub fn with_values(mut self, name : &str, values : Vec<f32>) -> Self {
+        self.params.push((name.to_string(), ParamRange::Values(values)));
+        self
+    }
+
+    pub fn with_range(mut self, name : &str, min : f32, max : f32, steps : u32) -> Self {
+        self.params.push((name.to_string(), ParamRange::Range { min, max, steps }));
+        self
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.params.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    //every combination of the grid values
+    pub fn grid(&self) -> Vec<Params> {
+        self.params.iter().fold(vec![Params::new()], |combinations, (name, range)| {
+            combinations.iter().flat_map(|params| range.grid_values().into_iter().map(move |value| {
+                let mut params = params.clone();
+                params.insert(name.clone(), value);
+                params
+            })).collect()
+        })
+    }
+
+    pub fn sample(&self, rng : &mut StdRng) -> Option<Params> {
+        self.params.iter().map(|(name, range)| Some((name.clone(), range.sample(rng)?))).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchMethod {
+    Grid,
+    Random { samples: u32, seed: u64 },
+}
+
+//how a configuration is scored over its runs. Higher is better.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Objective {
+    //mean final capital
+    FinalCapital,
+    //mean over the runs of the Sharpe ratio of the per-iteration returns
+    //(losing everything has no Sharpe ratio: a failed run scores 1.0 less than the worst completed one, and at most -1.0)
+    Sharpe,
+    //mean relative return divided by the mean max drawdown (at least 1%, so that a lucky flat run doesn't score infinity)
+    DrawdownAdjustedReturn,
+}
+
+fn sharpe(result : &RunResult) -> f32 {
+    let mut previous = result.starting_capital;
+    let mut returns = Vec::new();
+    for capital in result.capital_history.iter() {
+        if previous > 0.0 {
+            returns.push(capital / previous - 1.0);
+        }
+        previous = *capital;
+    }
+    if returns.len() < 2 {
+        return 0.0;
+    }
+    let mean = returns.iter().sum::<f32>() / returns.len() as f32;
+    let std_dev = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f32>() / (returns.len() - 1) as f32).sqrt();
+    if std_dev > 0.0 { mean / std_dev } else { 0.0 }
+}
+
+impl Objective {
+
+    //a failed run counts as if the strategy lost everything it started with
+    pub fn score(&self, runs : &[Result<RunResult, String>]) -> f32 {
+        let completed: Vec<&RunResult> = runs.iter()
+            .filter_map(|r| r.as_ref().ok())
+            .filter(|r| !matches!(r.reason, StopReason::StrategyPanicked(_)))
+            .collect();
+        if runs.is_empty() {
+            return f32::NEG_INFINITY;
+        }
+        let failed = (runs.len() - completed.len()) as f32;
+        let n = runs.len() as f32;
+
+        match self {
+            Objective::FinalCapital => completed.iter().map(|r| r.final_capital).sum::<f32>() / n,
+            Objective::Sharpe => {
+                let ratios: Vec<f32> = completed.iter().map(|r| sharpe(r)).collect();
+                let failed_ratio = ratios.iter().copied().fold(0.0, f32::min) - 1.0;
+                (ratios.iter().sum::<f32>() + failed * failed_ratio) / n
+            }
+            Objective::DrawdownAdjustedReturn => {
+                let relative_return = completed.iter()
+                    .map(|r| if r.starting_capital > 0.0 { r.pnl() / r.starting_capital } else { 0.0 })
+                    .sum::<f32>() - failed;
+                let drawdown = completed.iter().map(|r| r.max_drawdown()).sum::<f32>() + failed;
+                (relative_return / n) / (drawdown / n).max(0.01)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    pub rank: usize,
+    pub params: Params,
+    pub train_score: f32,
+    //only with test seeds
+    pub test_score: Option<f32>,
+    pub mean_final_capital: f32,
+    pub mean_max_drawdown: f32,
+    pub failed_runs: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptimizationReport {
+    pub objective: Objective,
+    pub param_names: Vec<String>,
+    //the worlds every configuration was run in, so that the search can be repeated
+    pub train_seeds: Vec<u64>,
+    pub test_seeds: Vec<u64>,
+    //best train score first
+    pub evaluations: Vec<Evaluation>,
+}
+
+impl OptimizationReport {
+
+    pub fn best(&self) -> Option<&Evaluation> {
+        self.evaluations.first()
+    }
+
+    //the seeds go in the last two columns, separated by spaces
+    pub fn write_csv(&self, path : impl AsRef<Path>) -> io::Result<()> {
+        let join = |seeds : &[u64]| seeds.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(" ");
+        let header: Vec<String> = std::iter::once("rank".to_string())
+            .chain(self.param_names.iter().cloned())
+            .chain(["train_score", "test_score", "mean_final_capital", "mean_max_drawdown", "failed_runs", "train_seeds", "test_seeds"].iter().map(|c| c.to_string()))
+            .collect();
+
+        let rows: Vec<Vec<String>> = self.evaluations.iter().map(|evaluation| {
+            std::iter::once(evaluation.rank.to_string())
+                .chain(self.param_names.iter().map(|name| evaluation.params.get(name).map_or(String::new(), |v| v.to_string())))
+                .chain([
+                    evaluation.train_score.to_string(),
+                    evaluation.test_score.map_or(String::new(), |s| s.to_string()),
+                    evaluation.mean_final_capital.to_string(),
+                    evaluation.mean_max_drawdown.to_string(),
+                    evaluation.failed_runs.to_string(),
+                    join(&self.train_seeds),
+                    join(&self.test_seeds),
+                ])
+                .collect()
+        }).collect();
+        write_csv(path, header, rows)
+    }
+}
+
+//Searches the parameters of a strategy. `factory` turns a set of parameters into a strategy; every configuration
+//is run on all the train seeds (and then on the test seeds, if any) and ranked by the objective on the train seeds.
+//Keeping some seeds for the test tells whether the best configuration is good or just fits the train worlds.
+pub struct Optimizer {
+    space: ParameterSpace,
+    method: SearchMethod,
+    objective: Objective,
+    train_seeds: Vec<u64>,
+    test_seeds: Vec<u64>,
+    world: Box<dyn Fn(u64) -> SimulationBuilder + Sync>,
+    factory: Box<dyn Fn(&Params) -> Box<dyn Fn(&mut Trader)> + Sync>,
+    stop: StopCondition,
+}
+
+impl Optimizer {
+
+    pub fn new(world : impl Fn(u64) -> SimulationBuilder + Sync + 'static, factory : impl Fn(&Params) -> Box<dyn Fn(&mut Trader)> + Sync + 'static, space : ParameterSpace) -> Self {
+        Optimizer {
+            space,
+            method: SearchMethod::Grid,
+            objective: Objective::FinalCapital,
+            train_seeds: derive_seeds(0, 10),
+            test_seeds: Vec::new(),
+            world: Box::new(world),
+            factory: Box::new(factory),
+            stop: default_batch_stop(),
+        }
+    }
+
+    pub fn with_method(mut self, method : SearchMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn with_objective(mut self, objective : Objective) -> Self {
+        self.objective = objective;
+        self
+    }
+
+    pub fn with_train_seeds(mut self, seeds : Vec<u64>) -> Self {
+        self.train_seeds = seeds;
+        self
+    }
+
+    pub fn with_test_seeds(mut self, seeds : Vec<u64>) -> Self {
+        self.test_seeds = seeds;
+        self
+    }
+
+    //`runs` train seeds and `test_runs` test seeds, all derived from `master_seed` and never overlapping
+    pub fn with_split(mut self, master_seed : u64, runs : u32, test_runs : u32) -> Self {
+        let mut seeds = derive_seeds(master_seed, runs + test_runs);
+        self.test_seeds = seeds.split_off(runs as usize);
+        self.train_seeds = seeds;
+        self
+    }
+
+    pub fn with_stop_condition(mut self, stop : StopCondition) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    fn candidates(&self) -> Vec<Params> {
+        match self.method {
+            SearchMethod::Grid => self.space.grid(),
+            SearchMethod::Random { samples, seed } => {
+                let mut rng = StdRng::seed_from_u64(seed);
+                (0..samples).filter_map(|_| self.space.sample(&mut rng)).collect()
+            }
+        }
+    }
+
+    //every candidate on every seed, all in the same pool of workers: runs[i][j] is candidate i on seeds[j]
+    fn run_all(&self, candidates : &[Params], seeds : &[u64]) -> Vec<Vec<Result<RunResult, String>>> {
+        if seeds.is_empty() {
+            return vec![Vec::new(); candidates.len()];
+        }
+        //run_in_parallel hands out numbers: here they are the positions in the candidates × seeds table
+        let jobs: Vec<u64> = (0..(candidates.len() * seeds.len()) as u64).collect();
+        let mut results = run_in_parallel(&jobs, |job| {
+            let (candidate, seed) = (job as usize / seeds.len(), seeds[job as usize % seeds.len()]);
+            run_seed(&*self.world, seed, (self.factory)(&candidates[candidate]), &self.stop)
+        }).into_iter();
+        candidates.iter().map(|_| results.by_ref().take(seeds.len()).collect()).collect()
+    }
+
+    pub fn run(&self) -> OptimizationReport {
+        let candidates = self.candidates();
+        let train = self.run_all(&candidates, &self.train_seeds);
+        let test = self.run_all(&candidates, &self.test_seeds);
+
+        let mut evaluations: Vec<Evaluation> = candidates.into_iter().zip(train).zip(test).map(|((params, runs), test_runs)| {
+            let completed: Vec<&RunResult> = runs.iter().filter_map(|r| r.as_ref().ok()).collect();
+            let mean = |values : Vec<f32>| if values.is_empty() { 0.0 } else { values.iter().sum::<f32>() / values.len() as f32 };
+
+            Evaluation {
+                rank: 0,
+                train_score: self.objective.score(&runs),
+                test_score: (!self.test_seeds.is_empty()).then(|| self.objective.score(&test_runs)),
+                mean_final_capital: mean(completed.iter().map(|r| r.final_capital).collect()),
+                mean_max_drawdown: mean(completed.iter().map(|r| r.max_drawdown()).collect()),
+                failed_runs: runs.iter().filter(|r| r.as_ref().map_or(true, |r| matches!(r.reason, StopReason::StrategyPanicked(_)))).count(),
+                params,
+            }
+        }).collect();
+
+        evaluations.sort_by(|a, b| b.train_score.partial_cmp(&a.train_score).unwrap_or(std::cmp::Ordering::Equal));
+        for (i, evaluation) in evaluations.iter_mut().enumerate() {
+            evaluation.rank = i + 1;
+        }
+
+        OptimizationReport {
+            objective: self.objective,
+            param_names: self.space.names().iter().map(|n| n.to_string()).collect(),
+            train_seeds: self.train_seeds.clone(),
+            test_seeds: self.test_seeds.clone(),
+            evaluations,
+        }
+    }
+}
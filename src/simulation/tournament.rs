@@ -0,0 +1,289 @@
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::simulation::monte_carlo::{default_batch_stop, derive_seeds, run_seed, Distribution};
+use crate::simulation::{write_csv, SimulationBuilder};
+use crate::trader::Trader;
+use crate::trader::trader_run::{RunResult, StopCondition, StopReason};
+use crate::trader::trader_sync::run_in_parallel;
+
+//one strategy in one world
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub seed: u64,
+    pub strategy: String,
+    pub result: Result<RunResult, String>,
+}
+
+impl Match {
+
+    //None if the world couldn't be built or the strategy panicked: such a match doesn't count in the comparisons
+    pub fn pnl(&self) -> Option<f32> {
+        match &self.result {
+            Ok(result) if !matches!(result.reason, StopReason::StrategyPanicked(_)) => Some(result.pnl()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standing {
+    pub rank: usize,
+    pub strategy: String,
+    pub pnl: Option<Distribution>,
+    pub max_drawdown: Option<Distribution>,
+    pub failed_runs: usize,
+    //the seeds in which the strategy did best (ties count for everybody)
+    pub wins: usize,
+}
+
+//Paired t-test on the P&L of two strategies over the seeds where both completed.
+//A small p-value means the difference is unlikely to be luck.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairedComparison {
+    pub first: String,
+    pub second: String,
+    pub samples: usize,
+    //mean of first - second
+    pub mean_difference: f32,
+    pub t_statistic: f32,
+    pub p_value: f32,
+}
+
+impl PairedComparison {
+
+    pub fn is_significant(&self, alpha : f32) -> bool {
+        self.p_value < alpha
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TournamentReport {
+    pub seeds: Vec<u64>,
+    pub matches: Vec<Match>,
+    //best mean P&L first
+    pub leaderboard: Vec<Standing>,
+    //one for every pair of strategies, in leaderboard order
+    pub comparisons: Vec<PairedComparison>,
+}
+
+impl TournamentReport {
+
+    pub fn winner(&self) -> Option<&Standing> {
+        self.leaderboard.first()
+    }
+
+    pub fn comparison(&self, first : &str, second : &str) -> Option<&PairedComparison> {
+        self.comparisons.iter().find(|c| (c.first == first && c.second == second) || (c.first == second && c.second == first))
+    }
+
+    //one line per match, with the seed of its world; the P&L is empty for the matches that don't count
+    pub fn write_csv(&self, path : impl AsRef<Path>) -> io::Result<()> {
+        let header: Vec<String> = ["seed", "strategy", "pnl", "final_capital", "max_drawdown"].iter().map(|c| c.to_string()).collect();
+        let rows: Vec<Vec<String>> = self.matches.iter().map(|m| {
+            let result = m.result.as_ref().ok();
+            vec![
+                m.seed.to_string(),
+                m.strategy.replace(',', ";"),
+                m.pnl().map_or(String::new(), |p| p.to_string()),
+                result.map_or(String::new(), |r| r.final_capital.to_string()),
+                result.map_or(String::new(), |r| r.max_drawdown().to_string()),
+            ]
+        }).collect();
+        write_csv(path, header, rows)
+    }
+}
+
+//Lanczos approximation (g = 7)
+fn ln_gamma(x : f64) -> f64 {
+    static COEFFICIENTS : [f64; 9] = [
+        0.999_999_999_999_809_9, 676.520_368_121_885_1, -1_259.139_216_722_402_8, 771.323_428_777_653_1,
+        -176.615_029_162_140_6, 12.507_343_278_686_905, -0.138_571_095_265_720_12, 9.984_369_578_019_572e-6, 1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        return (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = COEFFICIENTS.iter().enumerate().skip(1).fold(COEFFICIENTS[0], |acc, (i, c)| acc + c / (x + i as f64));
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+//continued fraction of the incomplete beta function (modified Lentz)
+fn beta_continued_fraction(a : f64, b : f64, x : f64) -> f64 {
+    let tiny = 1e-300;
+    let (qab, qap, qam) = (a + b, a + 1.0, a - 1.0);
+    let mut c = 1.0;
+    let mut d = 1.0 - qab * x / qap;
+    d = 1.0 / if d.abs() < tiny { tiny } else { d };
+    let mut h = d;
+    for m in 1..=300 {
+        let m = m as f64;
+        let aa = m * (b - m) * x / ((qam + 2.0 * m) * (a + 2.0 * m));
+        d = 1.0 + aa * d;
+        d = 1.0 / if d.abs() < tiny { tiny } else { d };
+        c = 1.0 + aa / c;
+        c = if c.abs() < tiny { tiny } else { c };
+        h *= d * c;
+
+        let aa = -(a + m) * (qab + m) * x / ((a + 2.0 * m) * (qap + 2.0 * m));
+        d = 1.0 + aa * d;
+        d = 1.0 / if d.abs() < tiny { tiny } else { d };
+        c = 1.0 + aa / c;
+        c = if c.abs() < tiny { tiny } else { c };
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < 1e-14 {
+            break;
+        }
+    }
+    h
+}
+
+//regularized incomplete beta function I_x(a, b)
+fn incomplete_beta(a : f64, b : f64, x : f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_continued_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+//two-sided p-value of Student's t with `dof` degrees of freedom
+fn student_t_p_value(t : f64, dof : f64) -> f64 {
+    incomplete_beta(dof / 2.0, 0.5, dof / (dof + t * t))
+}
+
+pub fn paired_t_test(first : &str, second : &str, differences : &[f32]) -> PairedComparison {
+    let n = differences.len();
+    let mean = if n > 0 { differences.iter().map(|d| *d as f64).sum::<f64>() / n as f64 } else { 0.0 };
+    let (t, p) = if n < 2 {
+        (0.0, 1.0)
+    } else {
+        let variance = differences.iter().map(|d| (*d as f64 - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+        let std_error = (variance / n as f64).sqrt();
+        if std_error == 0.0 {
+            //the same difference every time: either no difference at all, or a certain one
+            if mean == 0.0 { (0.0, 1.0) } else { (f64::INFINITY.copysign(mean), 0.0) }
+        } else {
+            let t = mean / std_error;
+            (t, student_t_p_value(t, (n - 1) as f64))
+        }
+    };
+    PairedComparison {
+        first: first.to_string(),
+        second: second.to_string(),
+        samples: n,
+        mean_difference: mean as f32,
+        t_statistic: t as f32,
+        p_value: p as f32,
+    }
+}
+
+//Runs several strategies on the same worlds: for every seed, each strategy gets a fresh trader and fresh markets built
+//from that seed. With synthetic markets the worlds are identical; the real markets only start from the same state.
+pub struct Tournament {
+    seeds: Vec<u64>,
+    world: Box<dyn Fn(u64) -> SimulationBuilder + Sync>,
+    strategies: Vec<(String, Arc<dyn Fn(&mut Trader) + Send + Sync>)>,
+    stop: StopCondition,
+}
+
+impl Tournament {
+
+    pub fn new(world : impl Fn(u64) -> SimulationBuilder + Sync + 'static) -> Self {
+        Tournament {
+            seeds: derive_seeds(0, 30),
+            world: Box::new(world),
+            strategies: Vec::new(),
+            stop: default_batch_stop(),
+        }
+    }
+
+    pub fn with_strategy(mut self, name : &str, strategy : impl Fn(&mut Trader) + Send + Sync + 'static) -> Self {
+        self.strategies.push((name.to_string(), Arc::new(strategy)));
+        self
+    }
+
+    pub fn with_runs(mut self, master_seed : u64, runs : u32) -> Self {
+        self.seeds = derive_seeds(master_seed, runs);
+        self
+    }
+
+    pub fn with_seeds(mut self, seeds : Vec<u64>) -> Self {
+        self.seeds = seeds;
+        self
+    }
+
+    pub fn with_stop_condition(mut self, stop : StopCondition) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    fn play(&self, seed : u64, name : &str, strategy : &Arc<dyn Fn(&mut Trader) + Send + Sync>) -> Match {
+        let strategy = Arc::clone(strategy);
+        let result = run_seed(&*self.world, seed, move |trader : &mut Trader| strategy(trader), &self.stop);
+        Match { seed, strategy: name.to_string(), result }
+    }
+
+    pub fn run(&self) -> TournamentReport {
+        //by_seed[i][j] is strategy j in the world of seed i: the matches are paired by position,
+        //so a seed given twice is just two more (identical) worlds
+        let by_seed: Vec<Vec<Match>> = run_in_parallel(&self.seeds, |seed| {
+            self.strategies.iter().map(|(name, strategy)| self.play(seed, name, strategy)).collect::<Vec<_>>()
+        });
+        let pnl_of = |strategy : usize, world : usize| by_seed[world][strategy].pnl();
+
+        let mut leaderboard: Vec<(usize, Standing)> = self.strategies.iter().enumerate().map(|(j, (name, _))| {
+            let own: Vec<&Match> = by_seed.iter().map(|matches| &matches[j]).collect();
+            let pnl: Vec<f32> = own.iter().filter_map(|m| m.pnl()).collect();
+            let drawdown: Vec<f32> = own.iter().filter_map(|m| m.result.as_ref().ok()).map(|r| r.max_drawdown()).collect();
+            let wins = (0..by_seed.len()).filter(|world| {
+                let best = (0..self.strategies.len()).filter_map(|other| pnl_of(other, *world)).fold(f32::NEG_INFINITY, f32::max);
+                pnl_of(j, *world).map_or(false, |p| p >= best)
+            }).count();
+            (j, Standing {
+                rank: 0,
+                strategy: name.clone(),
+                pnl: Distribution::from_samples(&pnl),
+                max_drawdown: Distribution::from_samples(&drawdown),
+                failed_runs: own.len() - pnl.len(),
+                wins,
+            })
+        }).collect();
+
+        //best mean P&L first; a strategy that never completed goes last
+        leaderboard.sort_by(|(_, a), (_, b)| {
+            let mean = |s : &Standing| s.pnl.as_ref().map_or(f32::NEG_INFINITY, |d| d.mean);
+            mean(b).partial_cmp(&mean(a)).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        for (i, (_, standing)) in leaderboard.iter_mut().enumerate() {
+            standing.rank = i + 1;
+        }
+
+        let mut comparisons = Vec::new();
+        for (i, (first, first_standing)) in leaderboard.iter().enumerate() {
+            for (second, second_standing) in leaderboard.iter().skip(i + 1) {
+                let differences: Vec<f32> = (0..by_seed.len())
+                    .filter_map(|world| Some(pnl_of(*first, world)? - pnl_of(*second, world)?))
+                    .collect();
+                comparisons.push(paired_t_test(&first_standing.strategy, &second_standing.strategy, &differences));
+            }
+        }
+
+        TournamentReport {
+            seeds: self.seeds.clone(),
+            matches: by_seed.into_iter().flatten().collect(),
+            leaderboard: leaderboard.into_iter().map(|(_, standing)| standing).collect(),
+            comparisons,
+        }
+    }
+}
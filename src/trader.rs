@@ -1,10 +1,26 @@
 mod trader_fancy_prints;
 pub mod trader_errors;
-
-use std::cell::RefCell;
+pub mod trader_liquidation;
+pub mod trader_quotes;
+pub mod trader_costs;
+pub mod trader_sync;
+pub mod trader_subscriptions;
+pub mod trader_events;
+pub mod trader_paper;
+pub mod trader_sizing;
+pub mod trader_run;
+pub mod trader_risk;
+pub mod trader_orders;
+pub mod trader_conversion;
+pub mod trader_rebalance;
+pub mod trader_execution;
+pub mod trader_negotiation;
+
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
 
+use std::fs;
 use std::fs::File;
 use std::rc::Rc;
 use colored::Colorize;
@@ -14,22 +30,33 @@ use market_common::good::good::Good;
 use market_common::good::good_kind::GoodKind;
 use market_common::good::good_kind::GoodKind::*;
 use market_common::market::good_label::GoodLabel;
-use market_common::market::{Market, MarketGetterError};
+use market_common::market::Market;
 use market_common::wait_one_day;
 use std::io::Write;
-use crate::trader::MarketKind::{BFB, DOGE, PANIC};
+use crate::trader::trader_costs::{ChargeLedger, CostModel, NoCosts, TradeSide};
 use crate::trader::trader_errors::{TraderDemandError, TraderSupplyError};
+use crate::trader::trader_events::EventFeed;
+use crate::trader::trader_paper::PaperLedger;
+use crate::trader::trader_risk::RiskManager;
+use crate::trader::trader_orders::{ConditionalOrder, OrderExecution, OrderId};
+use crate::trader::trader_execution::ParentOrder;
+use crate::trader::trader_negotiation::{Negotiation, NegotiationRecord};
+use crate::trader::trader_rebalance::{RebalanceReport, RebalanceSchedule};
+use crate::trader::trader_run::{FailurePolicy, StopCondition, StrategyFailure};
+use crate::trader::trader_subscriptions::Subscription;
 
 
 static TRADER_NAME : &str = "TASE Trader";
+pub(crate) static DEFAULT_OUTPUT_FILE : &str = "visualizer_data.txt";
 static MARKET_NOT_FOUND_MSG: fn(MarketKind) -> String = |market : MarketKind| {
     format!("Market \"{:?}\" not found!", market).red().to_string()
 };
-static DEFAULT_TRANSACTION_AMOUNT : f32 = 1000.0;
-static INFINITY: f32 = 1_000_000.;
+pub(crate) static DEFAULT_TRANSACTION_AMOUNT : f32 = 1000.0;
+//what save_data writes when a market can't quote a good
+pub(crate) static INFINITY: f32 = 1_000_000.;
 
 //this enum is utterly specific for our implementation and can't be generalized. Bad!
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum MarketKind {
     TASE,
     BOSE,
@@ -43,19 +70,70 @@ pub struct Trader {
     closure: Box<dyn Fn(&mut Trader)>,
     closure_just_modified: bool,
 
+    //state shared between the strategy and run_until()
+    days_elapsed: u32,
+    strategy_done: bool,
+    run_errors: Vec<String>,
+    failure_policy: FailurePolicy,
+    strategy_failures: Vec<StrategyFailure>,
+
     //I hate this because Good already contains the GoodKind. If I used an u32 I would get issues in the buy function
     owned_goods: HashMap<GoodKind, Good>,
 
     markets: HashMap<MarketKind, Rc<RefCell<dyn Market>>>,
+    //which market gets notified of which market's events, with the switch of the relay doing it.
+    //Kept up to date by add_market() and remove_market().
+    subscriptions: HashMap<Subscription, Rc<Cell<bool>>>,
+
+    //what the markets broadcast, as seen by the trader's listeners (one per listened market)
+    event_feed: Rc<RefCell<EventFeed>>,
+    listeners: Vec<(MarketKind, usize)>,
+    auto_listen: bool,
 
     pending_buy_orders: Vec<(MarketKind, String)>,
     pending_sell_orders: Vec<(MarketKind, String)>,
 
-    //This is a very important and crucial field. It determines whether the trader gets free money after each transaction or not.
+    //This is a very important and crucial field. It determines how much free money the trader gets after each transaction,
+    //but only once with_amazingness_rebate() turns it into a rebate: the default cost model charges (and gives) nothing.
     amazingness: f32,
 
+    cost_model: Box<dyn CostModel>,
+    charges: ChargeLedger,
+
+    //Some while paper trading: it holds the virtual goods the strategy trades with, the owned goods stay untouched
+    paper: Option<PaperLedger>,
+
+    //Some once risk limits are set: every order is checked against them
+    risk: Option<RiskManager>,
+
+    //standing orders, checked after every day (and after every trade if orders_after_trades)
+    conditional_orders: Vec<ConditionalOrder>,
+    order_executions: Vec<OrderExecution>,
+    next_order_id: OrderId,
+    orders_after_trades: bool,
+    evaluating_orders: bool,
+
+    //Some if run_until() has to rebalance the portfolio every few days
+    rebalance_schedule: Option<RebalanceSchedule>,
+    rebalance_reports: Vec<RebalanceReport>,
+
+    //large orders traded a slice per day by wait()
+    executions: Vec<ParentOrder>,
+    finished_executions: Vec<ParentOrder>,
+
+    //Some if buy() and sell() haggle for a better price than the quote
+    negotiation: Option<Negotiation>,
+    negotiation_records: Vec<NegotiationRecord>,
+
+    //where the visualizer data is written when the trader is dropped. None: nowhere.
+    output_file: Option<String>,
+    //the master seed of the simulation, if the trader was built from one. It is written along with the visualizer data.
+    simulation_seed: Option<u64>,
+
     // DATA for visualizer
+    //one entry per attached market, in the order they were attached: data_index says which is which
     pub data: Vec<Vec<HashMap<GoodKind, Vec<f32>>>>,
+    data_index: HashMap<MarketKind, usize>,
     pub liquidity: HashMap<GoodKind, Vec<f32>>
 }
 
@@ -64,12 +142,14 @@ impl Debug for Trader {
         if f.alternate() {
             return write!(
                 f,
-                "➤ Trader Status:\n • Markets: {}\n • Money: {}",
+                "➤ Trader Status:\n • Markets: {}\n • Money: {}\n • Fees paid: {}\n • Rebates received: {}",
                 self.markets
                     .iter()
                     .map(|(_, t)| format!("\"{}\", ", (**t).borrow().get_name()))
                     .collect::<String>(),
-                self.owned_goods.get(&EUR).unwrap()
+                self.owned_goods.get(&EUR).unwrap(),
+                self.charges.total_fees(),
+                self.charges.total_rebates()
             );
         }
         write!(
@@ -83,35 +163,52 @@ impl Debug for Trader {
 impl Drop for Trader {
     fn drop(&mut self) {
         //println!("{:?}", self.liquidity);
+        let path = match &self.output_file {
+            Some(path) => path.clone(),
+            None => return,
+        };
+
         // Save data to file
-        let file = File::create("visualizer_data.txt");
+        let file = File::create(&path);
 
         match file {
             Ok(mut file) => {
 
-                let serialized_data = serde_json::to_string(&self.data);
-                match serialized_data {
+                match serde_json::to_string(&self.data) {
                     Ok(data) => {
-                        write!(file, "{}", data).expect("The trader experienced an internal error while writing to \"visualizer_data.txt\".");
+                        write!(file, "{}", data).expect(format!("The trader experienced an internal error while writing to \"{}\".", path).as_str());
                     }
                     Err(e) => {
                         println!("{}", e.to_string());
-                        println!("TRADER: couldn't serialize the data. The last state was NOT recorded to \"visualizer_data.txt\"");
+                        println!("TRADER: couldn't serialize the data. The last state was NOT recorded to \"{}\"", path);
                     }
                 }
 
             }
             Err(e) => {
-                println!("TRADER: couldn't open \"visualizer_data.txt\" because of one out of various reasons. Stacktrace:");
+                println!("TRADER: couldn't open \"{}\" because of one out of various reasons. Stacktrace:", path);
                 println!("{}", e.to_string());
             }
         }
+
+        //the visualizer file keeps its format: the seed of a simulation goes next to it
+        if let Some(seed) = self.simulation_seed {
+            let seed_path = seed_file(&path);
+            if let Err(e) = fs::write(&seed_path, serde_json::json!({ "seed": seed }).to_string()) {
+                println!("TRADER: couldn't write the seed to \"{}\": {}", seed_path, e);
+            }
+        }
     }
 }
 
+//where a seeded trader writes its seed, next to the visualizer file
+pub fn seed_file(output_file : &str) -> String {
+    format!("{}.seed.json", output_file)
+}
+
 impl Trader {
 
-    fn initialize_data(&mut self, _market: &Rc<RefCell<dyn Market>>)  {
+    fn initialize_data(&mut self, kind: MarketKind)  {
         let mut res = Vec::new();
         let h: HashMap<GoodKind, Vec<f32>> = HashMap::new();
         res.push(h); // sell
@@ -134,32 +231,29 @@ impl Trader {
             op.insert(GoodKind::YUAN, Vec::new());
         }
         
+        self.data_index.insert(kind, self.data.len());
         self.data.push(res);
     }
 
-    pub fn new() -> Self {
-        let mut owned_goods = HashMap::new();
-        owned_goods.insert(EUR, Good::new(EUR, 1000.0));
-        owned_goods.insert(USD, Good::new(USD, 0.0));
-        owned_goods.insert(YEN,  Good::new(YEN, 0.0));
-        owned_goods.insert(YUAN, Good::new(YUAN, 0.0));
-
-        let mut liq: HashMap<GoodKind, Vec<f32>> = HashMap::new();
-        for (gk, _) in owned_goods.iter() {
-            liq.insert(*gk, Vec::new());
+    //the series after the removed ones move up by one
+    fn remove_data(&mut self, kind: MarketKind) {
+        if let Some(index) = self.data_index.remove(&kind) {
+            self.data.remove(index);
+            for i in self.data_index.values_mut() {
+                if *i > index {
+                    *i -= 1;
+                }
+            }
         }
+    }
 
-        Trader {
-            closure: Box::new(|_| {}),
-            closure_just_modified: false,
-            owned_goods,
-            markets: HashMap::new(),
-            pending_buy_orders: Vec::new(),
-            pending_sell_orders: Vec::new(),
-            amazingness: 1.0,
-            data: Vec::new(),
-            liquidity: liq 
-        }
+    //where the market's series are in `data`
+    pub fn data_index(&self, kind: MarketKind) -> Option<usize> {
+        self.data_index.get(&kind).copied()
+    }
+
+    pub fn new() -> Self {
+        Self::new_super_duper_amazing_trader(1.0)
     }
 
     pub fn new_super_duper_amazing_trader(amazingness: f32) -> Self {
@@ -177,19 +271,45 @@ impl Trader {
         Trader {
             closure: Box::new(|_| {}),
             closure_just_modified: false,
+            days_elapsed: 0,
+            strategy_done: false,
+            run_errors: Vec::new(),
+            failure_policy: FailurePolicy::default(),
+            strategy_failures: Vec::new(),
             owned_goods,
             markets: HashMap::new(),
+            subscriptions: HashMap::new(),
+            event_feed: Rc::new(RefCell::new(EventFeed::default())),
+            listeners: Vec::new(),
+            auto_listen: false,
             pending_buy_orders: Vec::new(),
             pending_sell_orders: Vec::new(),
             amazingness,
+            cost_model: Box::new(NoCosts),
+            charges: ChargeLedger::default(),
+            paper: None,
+            risk: None,
+            conditional_orders: Vec::new(),
+            order_executions: Vec::new(),
+            next_order_id: 0,
+            orders_after_trades: false,
+            evaluating_orders: false,
+            rebalance_schedule: None,
+            rebalance_reports: Vec::new(),
+            executions: Vec::new(),
+            finished_executions: Vec::new(),
+            negotiation: None,
+            negotiation_records: Vec::new(),
+            output_file: Some(DEFAULT_OUTPUT_FILE.to_string()),
+            simulation_seed: None,
             data: Vec::new(),
+            data_index: HashMap::new(),
             liquidity: liq 
         }
     }
 
     pub fn with_market(mut self, kind: MarketKind, market: Rc<RefCell<dyn Market>>) -> Self {
-        self.initialize_data(&market);
-        self.markets.insert(kind, market);
+        self.add_market(kind, market);
         self
     }
 
@@ -203,39 +323,46 @@ impl Trader {
         self
     }
 
+    //the visualizer data goes to "visualizer_data.txt" unless told otherwise
+    pub fn with_output_file(mut self, path : &str) -> Self {
+        self.output_file = Some(path.to_string());
+        self
+    }
 
-    //I'm pretty sure this is absolutely terrible, but I really liked the idea of having a modifiable closure inside the trader.
-    //I'll trust LegionMammal978 on this one: https://users.rust-lang.org/t/pass-a-closure-that-takes-a-mutable-reference-to-self/73843/3
-    //This kind of backfired because given the fact that absolutely "noone" uses closures in this way, I had to write some acrobatic code to make it work.
-    pub fn run(&mut self, iterations: i32){
-
-        let mut i = 0;
-
-        while i < iterations {
+    pub fn without_output_file(mut self) -> Self {
+        self.output_file = None;
+        self
+    }
 
-            //steal the struct's operation and replace it with a temporary value:
-            let closure = std::mem::replace(&mut self.closure, Box::new(|_| { println!("the closure was replaced but not given back")}));
-            closure(self);
+    pub fn with_simulation_seed(mut self, seed : u64) -> Self {
+        self.simulation_seed = Some(seed);
+        self
+    }
 
-            if self.closure_just_modified {
-                self.closure_just_modified = false;
-                //since the closure was modified, it means that the trader got in a new state. Therefore, we have to call the closure again.
-                continue;
-            }
+    pub fn simulation_seed(&self) -> Option<u64> {
+        self.simulation_seed
+    }
 
-            //
-            self.closure = Box::new(closure);
-            i += 1;
 
-        }
+    //I'm pretty sure this is absolutely terrible, but I really liked the idea of having a modifiable closure inside the trader.
+    //I'll trust LegionMammal978 on this one: https://users.rust-lang.org/t/pass-a-closure-that-takes-a-mutable-reference-to-self/73843/3
+    //This kind of backfired because given the fact that absolutely "noone" uses closures in this way, I had to write some acrobatic code to make it work.
+    pub fn run(&mut self, iterations: i32){
+        self.run_until(StopCondition::MaxIterations(iterations.max(0) as u32));
     }
 
     fn save_data(&mut self) {
-        let ordered_markets = vec![MarketKind::BFB, MarketKind::BOSE, MarketKind::DOGE];
-        for (market_index, m) in ordered_markets.iter().enumerate() {
-            let market = self.markets.get(m).unwrap();
-            for (kind, _) in self.owned_goods.iter() {
-                if *kind == EUR {
+        //in the order the markets were attached, which is also the order of `data`
+        let mut attached: Vec<(MarketKind, usize)> = self.data_index.iter().map(|(k, i)| (*k, *i)).collect();
+        attached.sort_by_key(|(_, index)| *index);
+        for (m, market_index) in attached {
+            let market = match self.markets.get(&m) {
+                Some(market) => market,
+                None => continue,
+            };
+            //always ask for the goods in the same order, so that a recorded market can be replayed
+            for kind in [USD, YEN, YUAN].iter() {
+                if !self.owned_goods.contains_key(kind) {
                     continue;
                 }
                 // SELL
@@ -272,33 +399,25 @@ impl Trader {
         self.closure_just_modified = true;
     }
 
+    //the goods the strategy sees: the virtual ones while paper trading
+    pub(crate) fn holdings(&self) -> &HashMap<GoodKind, Good> {
+        match &self.paper {
+            Some(ledger) => ledger.goods(),
+            None => &self.owned_goods,
+        }
+    }
+
     pub fn get_owned_good_qty(&self, kind : GoodKind) -> f32 {
-        self.owned_goods.get(&kind).expect(format!("trader has no {}? Panic!", kind).as_str()).get_qty()
+        self.holdings().get(&kind).expect(format!("trader has no {}? Panic!", kind).as_str()).get_qty()
     }
 
     //I (Dennis) renamed "buy" to "supply" because I was getting crazy in distinguishing between "buy" and "sell"
     pub fn get_supply_price(&self, market : MarketKind, kind : GoodKind) -> Result<f32, TraderSupplyError> {
-        match self.markets.get(&market).expect(MARKET_NOT_FOUND_MSG(market).as_str()).borrow().get_buy_price(kind, DEFAULT_TRANSACTION_AMOUNT) {
-            Ok(price) => Ok(price),
-            Err(e) => {
-                match e {
-                    MarketGetterError::InsufficientGoodQuantityAvailable { ..} => Err(TraderSupplyError::MarketInsufficientSupply),
-                    _=> panic!("Unhandled error: {:?}", e)
-                }
-            }
-        }
+        Ok(self.markets.get(&market).expect(MARKET_NOT_FOUND_MSG(market).as_str()).borrow().get_buy_price(kind, DEFAULT_TRANSACTION_AMOUNT)?)
     }
 
     pub fn get_supply_price_qt(&self, market : MarketKind, kind : GoodKind, quantity : f32) -> Result<f32, TraderSupplyError> {
-        match self.markets.get(&market).expect(MARKET_NOT_FOUND_MSG(market).as_str()).borrow().get_buy_price(kind, quantity) {
-            Ok(price) => Ok(price),
-            Err(e) => {
-                match e {
-                    MarketGetterError::InsufficientGoodQuantityAvailable { ..} => Err(TraderSupplyError::MarketInsufficientSupply),
-                    _=> panic!("Unhandled error: {:?}", e)
-                }
-            }
-        }
+        Ok(self.markets.get(&market).expect(MARKET_NOT_FOUND_MSG(market).as_str()).borrow().get_buy_price(kind, quantity)?)
 
     }
 
@@ -323,16 +442,46 @@ impl Trader {
             .expect(format!("Couldn't get sell price for {} in market {:?}", kind, market).as_str())
     }
 
+    //what sell() and lock_without_selling() use: get_demand_price_qt panics when the market can't quote
+    fn checked_demand_price_qt(&self, market : MarketKind, kind : GoodKind, quantity : f32) -> Result<f32, TraderDemandError> {
+        Ok(self.get_market(market)?.borrow().get_sell_price(kind, quantity)?)
+    }
+
     //todo: abort the operation if you don't have enough money. Perhaps passing the "insufficientgoodquantityerror" to the output of this function?
     //returns an f32 representing the money you got from the transaction
     pub fn buy(&mut self, market : MarketKind, kind : GoodKind, amount : f32) -> Result<f32, TraderSupplyError> {
+        self.buy_measured(market, kind, amount).map(|(bought, _)| bought)
+    }
+
+    //buy() that also tells what the purchase cost: the agreed price plus the fees settled for it.
+    //Measured by the buy itself, so the conditional orders it triggers afterwards don't count.
+    pub(crate) fn buy_measured(&mut self, market : MarketKind, kind : GoodKind, amount : f32) -> Result<(f32, f32), TraderSupplyError> {
 
         let price = self.get_supply_price_qt(market, kind, amount)?;
+        self.check_risk(TradeSide::Buy, market, kind, amount, price)?;
+
+        if self.is_paper_trading() {
+            let fill = self.paper_buy(market, kind, amount, price)?;
+            //the notional the ledger settled, impact included: the same the live path records
+            self.record_risk_fill(TradeSide::Buy, market, fill.fill_price);
+            self.evaluate_orders_after_trade();
+            return Ok((fill.quantity, fill.fill_price + fill.charges.net()));
+        }
 
-        let token = self.get_market(market)?.borrow_mut()
-            .lock_buy(kind, amount, price, TRADER_NAME.to_string())?;
+        let charges = self.cost_model.charges(market, kind, TradeSide::Buy, price);
+        if self.get_owned_good_qty(EUR) < price + charges.net().max(0.0) {
+            return Err(TraderSupplyError::TraderInsufficientFunds);
+        }
 
-        let bought_goods = self.get_market(market)?.borrow_mut().buy(token, self.owned_goods.get_mut(&EUR).unwrap())?;
+        let (token, price) = self.negotiate_lock_buy(market, kind, amount, price)?;
+        //a better price means lower fees
+        let charges = self.cost_model.charges(market, kind, TradeSide::Buy, price);
+
+        let market_ref = self.get_market(market)?;
+        let bought_goods = {
+            let _own = self.own_call();
+            market_ref.borrow_mut().buy(token, self.owned_goods.get_mut(&EUR).unwrap())?
+        };
 
         //save value because the goods will lose ownership
         let value = bought_goods.get_qty();
@@ -340,17 +489,27 @@ impl Trader {
         self.owned_goods.get_mut(&kind).expect(format!("{} disappeard from the trader's internal hashmap. Panic!", kind).as_str())
             .merge(bought_goods).expect("Couldn't add the bought goods to the trader's internal hashmap. Panic!");
 
+        //the market filled: whatever happens with the fees, the trade stands
+        let charged = self.settle_charges(market, kind, TradeSide::Buy, price, charges);
+        self.record_risk_fill(TradeSide::Buy, market, price);
+
         self.save_data();
+        self.evaluate_orders_after_trade();
 
-        Ok(value)
+        Ok((value, price + charged))
     }
 
     pub fn lock_without_buying(&mut self, market : MarketKind, kind : GoodKind, amount : f32) -> Result<(String, f32), TraderSupplyError> {
 
         let price = self.get_supply_price_qt(market, kind, amount)?;
+        self.check_risk(TradeSide::Buy, market, kind, amount, price)?;
 
-        let token = self.get_market(market)?.borrow_mut()
-            .lock_buy(kind, amount, price, TRADER_NAME.to_string())?;
+        //paper trades never lock the real markets
+        if self.is_paper_trading() {
+            return Ok((self.paper_token(), price));
+        }
+
+        let (token, price) = self.negotiate_lock_buy(market, kind, amount, price)?;
         self.save_data();
 
         Ok((token, price))
@@ -358,10 +517,14 @@ impl Trader {
 
     pub fn lock_without_selling(&mut self, market : MarketKind, kind : GoodKind, amount : f32) -> Result<(String, f32), TraderDemandError> {
 
-        let price = self.get_demand_price_qt(market, kind, amount);
+        let price = self.checked_demand_price_qt(market, kind, amount)?;
+        self.check_risk(TradeSide::Sell, market, kind, amount, price)?;
 
-        let token = self.get_market(market)?.borrow_mut()
-            .lock_sell(kind, amount, price, TRADER_NAME.to_string())?;
+        if self.is_paper_trading() {
+            return Ok((self.paper_token(), price));
+        }
+
+        let (token, price) = self.negotiate_lock_sell(market, kind, amount, price)?;
         self.save_data();
 
         Ok((token, price))
@@ -375,28 +538,56 @@ impl Trader {
     //AI generated, should be fine
     //Nevermind it was not fine: Dennis fixed it.
     pub fn sell(&mut self, market : MarketKind, kind : GoodKind, amount : f32) -> Result<f32, TraderDemandError> {
+        self.sell_measured(market, kind, amount).map(|(proceeds, _)| proceeds)
+    }
 
-        let price = self.get_demand_price_qt(market, kind, amount);
+    //sell() that also tells what the trader really got: the proceeds minus the fees settled for them
+    pub(crate) fn sell_measured(&mut self, market : MarketKind, kind : GoodKind, amount : f32) -> Result<(f32, f32), TraderDemandError> {
 
-        let token = self.get_market(market)?
-            .borrow_mut()
-            .lock_sell(kind, amount, price, TRADER_NAME.to_string())?;
+        let price = self.checked_demand_price_qt(market, kind, amount)?;
+        self.check_risk(TradeSide::Sell, market, kind, amount, price)?;
+
+        if self.is_paper_trading() {
+            let fill = self.paper_sell(market, kind, amount, price)?;
+            self.record_risk_fill(TradeSide::Sell, market, fill.fill_price);
+            self.evaluate_orders_after_trade();
+            return Ok((fill.fill_price, fill.fill_price - fill.charges.net()));
+        }
 
-        let sold_goods = self.get_market(market)?
-            .borrow_mut()
-            .sell(token, self.owned_goods.get_mut(&kind).expect(format!("Trader has no {}", kind).as_str()))?;
+        //the fees are paid with the proceeds, so the trader has to be able to cover whatever the proceeds don't
+        let charges = self.cost_model.charges(market, kind, TradeSide::Sell, price);
+        if self.get_owned_good_qty(EUR) + price < charges.net() {
+            return Err(TraderDemandError::TraderInsufficientFunds);
+        }
+
+        let (token, price) = self.negotiate_lock_sell(market, kind, amount, price)?;
+        let charges = self.cost_model.charges(market, kind, TradeSide::Sell, price);
+
+        let market_ref = self.get_market(market)?;
+        let sold_goods = {
+            let _own = self.own_call();
+            market_ref.borrow_mut().sell(token, self.owned_goods.get_mut(&kind).expect(format!("Trader has no {}", kind).as_str()))?
+        };
         let value = sold_goods.get_qty();
 
         self.owned_goods.get_mut(&EUR).expect(format!("{} disappeard from the trader's internal hashmap. Panic!", kind).as_str())
             .merge(sold_goods).expect("Couldn't add the sold goods to the trader's internal hashmap. Panic!");
-        
+
+        let charged = self.settle_charges(market, kind, TradeSide::Sell, price, charges);
+        self.record_risk_fill(TradeSide::Sell, market, value);
+
         self.save_data();
-        Ok(value)
+        self.evaluate_orders_after_trade();
+        Ok((value, value - charged))
     }
 
     pub fn wait(&mut self){
         self.markets.values().for_each(|m| wait_one_day!(m));
+        self.days_elapsed += 1;
+        self.start_risk_day();
+        self.run_executions();
         self.save_data();
+        self.evaluate_orders();
     }
 
     pub fn wait_for(&mut self, days : u32){
@@ -500,117 +691,20 @@ impl Trader {
 
      */
 
-    //"cashout" all the owned goods, aka sell all the goods to the markets for euros. We'll automatically sell the goods to the highest bidder.
-    // UNFINISHED
-    pub fn bailout(&mut self) {
-        self.get_goods().iter().for_each(|g| {
-
-            if g.get_kind() == GoodKind::EUR {
-                return;
-            }
-
-            let mut amount = self.get_owned_good_qty(g.get_kind());
-
-            while amount > 0.0 {
-                let best_buyer = self.best_buyer_for(g.get_kind(), f32::min(amount, 10000.0));
-                self.sell(best_buyer, g.get_kind(), f32::min(amount, 10000.0)).unwrap();
-                amount -= f32::min(amount, 10000.0);
-            }
-
-        });
-    }
-
     pub fn get_goods(&mut self) -> Vec<Good> {
-        self.owned_goods.iter().map(|(_, g)| g.clone()).collect()
+        self.holdings().iter().map(|(_, g)| g.clone()).collect()
     }
 
     pub fn get_capital(&self) -> f32{
-        let mut capital = self.owned_goods.get(&EUR).unwrap().get_qty();
-        capital += self.owned_goods.get(&USD).unwrap().get_qty()/USD.get_default_exchange_rate();
-        capital += self.owned_goods.get(&YUAN).unwrap().get_qty()/YUAN.get_default_exchange_rate();
-        capital += self.owned_goods.get(&YEN).unwrap().get_qty()/YEN.get_default_exchange_rate();
+        let goods = self.holdings();
+        let mut capital = goods.get(&EUR).unwrap().get_qty();
+        capital += goods.get(&USD).unwrap().get_qty()/USD.get_default_exchange_rate();
+        capital += goods.get(&YUAN).unwrap().get_qty()/YUAN.get_default_exchange_rate();
+        capital += goods.get(&YEN).unwrap().get_qty()/YEN.get_default_exchange_rate();
 
         capital
     }
 
-    pub fn cheapest_supplier(&self, kind : GoodKind) -> MarketKind {
-
-        if self.markets.len() == 0 {
-            println!("{}", "The trader does not have any market. Defaulting \"cheapest supplier\" to DOGE".red());
-            return DOGE;
-        }
-
-        let mut cheapest_supplier = DOGE;
-        let lowest_price = f32::MAX;
-        self.markets.iter().for_each(|(marketkind, market)| {
-            //ignore bfb because of the bug
-            if marketkind == &BFB {
-                return;
-            }
-            if market.borrow().get_buy_price(kind, DEFAULT_TRANSACTION_AMOUNT).unwrap() < lowest_price {
-                cheapest_supplier = *marketkind;
-            }
-        });
-        cheapest_supplier
-    }
-
-    pub fn cheapest_supplier_for(&self, kind : GoodKind, quantity : f32) -> MarketKind {
-
-        if self.markets.len() == 0 {
-            println!("{}", "The trader does not have any market. Defaulting \"cheapest supplier\" to DOGE".red());
-            return DOGE;
-        }
-
-        let mut cheapest_supplier = DOGE;
-        let lowest_price = f32::MAX;
-        self.markets.iter().for_each(|(marketkind, market)| {
-            if market.borrow().get_buy_price(kind, quantity).unwrap() < lowest_price {
-                cheapest_supplier = *marketkind;
-            }
-        });
-        cheapest_supplier
-    }
-
-    //AI generated, should be OK
-    pub fn best_buyer(&self, kind : GoodKind) -> MarketKind {
-
-            if self.markets.len() == 0 {
-                println!("{}", "The trader does not have any market. Defaulting \"best buyer\" to DOGE".red());
-                return DOGE;
-            }
-
-            let mut best_buyer = DOGE;
-            let highest_price = f32::MIN;
-            self.markets.iter().for_each(|(marketkind, market)| {
-                if market.borrow().get_sell_price(kind, DEFAULT_TRANSACTION_AMOUNT).unwrap() > highest_price {
-                    best_buyer = *marketkind;
-                }
-            });
-            best_buyer
-    }
-
-    //AI generated, should be OK
-    pub fn best_buyer_for(&self, kind : GoodKind, quantity : f32) -> MarketKind {
-
-            if self.markets.len() == 0 {
-                panic!("{}", "The trader does not have any market. Defaulting \"best buyer\" to DOGE".red());
-            }
-
-            let mut best_buyer = PANIC;
-            let mut highest_price = f32::MIN;
-            self.markets.iter().for_each(|(marketkind, market)| {
-                let price = market.borrow().get_sell_price(kind, quantity).unwrap();
-                if price > highest_price && market.borrow().get_budget() > price {
-                    best_buyer = *marketkind;
-                    highest_price = price;
-                }
-            });
-            if best_buyer == PANIC {
-                panic!("no market has such amount of money.");
-            }
-            best_buyer
-    }
-
     pub fn get_good_qty(&self, market : MarketKind, kind : GoodKind) -> f32 {
 
         let mut quantity = 0.0;
@@ -0,0 +1,160 @@
+use market_common::good::good_kind::GoodKind;
+use market_common::good::good_kind::GoodKind::*;
+
+use crate::trader::{MarketKind, Trader};
+use crate::trader::trader_costs::TradeSide;
+use crate::trader::trader_errors::{TraderDemandError, TraderSupplyError};
+
+//every market trades goods against euros, so the useful routes are short: this is only a safety net
+static MAX_CONVERSION_HOPS : usize = 4;
+//below this quantity a hop is not worth doing
+static MIN_HOP_QTY : f32 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConversionHop {
+    pub market: MarketKind,
+    //Sell: `from` is sold for euros. Buy: euros are spent on `to`.
+    pub side: TradeSide,
+    pub from: GoodKind,
+    pub to: GoodKind,
+    pub quantity_in: f32,
+    //what the quotes promise, net of fees
+    pub expected_out: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionPlan {
+    pub from: GoodKind,
+    pub to: GoodKind,
+    pub quantity: f32,
+    pub hops: Vec<ConversionHop>,
+    //optimistic: every hop is priced on the markets as they are now, without the impact of the earlier hops
+    pub expected_output: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HopError {
+    Buy(TraderSupplyError),
+    Sell(TraderDemandError),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HopResult {
+    pub hop: ConversionHop,
+    //what was really put in (the output of the previous hop, which may differ from the plan)
+    pub quantity_in: f32,
+    //how much of `to` the trader gained, fees included
+    pub result: Result<f32, HopError>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionReport {
+    pub plan: ConversionPlan,
+    pub hops: Vec<HopResult>,
+    //how much of the target good the conversion produced (0.0 if it stopped halfway)
+    pub output: f32,
+}
+
+impl ConversionReport {
+
+    pub fn is_complete(&self) -> bool {
+        self.hops.len() == self.plan.hops.len() && self.hops.iter().all(|h| h.result.is_ok())
+    }
+
+    //how much less than expected was obtained, as a fraction of the expected output
+    pub fn slippage(&self) -> f32 {
+        if self.plan.expected_output > 0.0 {
+            1.0 - self.output / self.plan.expected_output
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Trader {
+
+    //the route from `quantity` of `from` to the largest quantity of `to`, trying every market at every hop.
+    //None if `to` can't be reached.
+    pub fn plan_conversion(&self, from : GoodKind, to : GoodKind, quantity : f32) -> Option<ConversionPlan> {
+        if from == to {
+            return Some(ConversionPlan { from, to, quantity, hops: Vec::new(), expected_output: quantity });
+        }
+        let mut best: Option<(f32, Vec<ConversionHop>)> = None;
+        self.explore_conversions(from, quantity, to, &mut Vec::new(), &mut best);
+        best.map(|(expected_output, hops)| ConversionPlan { from, to, quantity, hops, expected_output })
+    }
+
+    fn explore_conversions(&self, good : GoodKind, quantity : f32, target : GoodKind, path : &mut Vec<ConversionHop>, best : &mut Option<(f32, Vec<ConversionHop>)>) {
+        if good == target && !path.is_empty() {
+            if best.as_ref().map_or(true, |(output, _)| quantity > *output) {
+                *best = Some((quantity, path.clone()));
+            }
+            return;
+        }
+        if path.len() >= MAX_CONVERSION_HOPS || quantity < MIN_HOP_QTY {
+            return;
+        }
+
+        //the same market data is used for all hops: the quotes don't know about the earlier hops of the route
+        let mut markets: Vec<MarketKind> = self.markets.keys().copied().collect();
+        markets.sort();
+        for market in markets {
+            let next: Vec<(TradeSide, GoodKind, f32)> = if good == EUR {
+                [USD, YEN, YUAN].iter()
+                    //going back to a good already sold is never worth it
+                    .filter(|kind| !path.iter().any(|h| h.from == **kind))
+                    .map(|kind| (TradeSide::Buy, *kind, self.max_buy_qty_for_budget(market, *kind, quantity)))
+                    .collect()
+            } else if path.iter().any(|h| h.market == market && h.to == good) {
+                //selling back where the good was just bought: the quote wouldn't know about that purchase
+                Vec::new()
+            } else {
+                self.sell_yield(market, good, quantity).map_or(Vec::new(), |eur| vec![(TradeSide::Sell, EUR, eur)])
+            };
+
+            for (side, to, out) in next {
+                if out < MIN_HOP_QTY {
+                    continue;
+                }
+                path.push(ConversionHop { market, side, from: good, to, quantity_in: quantity, expected_out: out });
+                self.explore_conversions(to, out, target, path, best);
+                path.pop();
+            }
+        }
+    }
+
+    //plans the conversion and carries it out hop by hop, each hop using what the previous one really produced
+    pub fn convert(&mut self, from : GoodKind, to : GoodKind, quantity : f32) -> Option<ConversionReport> {
+        let plan = self.plan_conversion(from, to, quantity.min(self.get_owned_good_qty(from)))?;
+        Some(self.execute_conversion(plan))
+    }
+
+    pub fn execute_conversion(&mut self, plan : ConversionPlan) -> ConversionReport {
+        let mut hops = Vec::new();
+        let mut quantity = plan.quantity;
+
+        for hop in plan.hops.iter() {
+            //only what the previous hop produced goes on: the rest of the holdings (the euros above all) is not part of the conversion
+            let quantity_in = quantity;
+            let before = self.get_owned_good_qty(hop.to);
+
+            //a market that stopped quoting since the plan is a failed sell like any other
+            let result = match hop.side {
+                TradeSide::Sell => self.sell(hop.market, hop.from, quantity_in).map_err(HopError::Sell),
+                TradeSide::Buy => self.buy_with_budget(hop.market, hop.to, quantity_in).map_err(HopError::Buy),
+            };
+            //the fees are settled apart from the trade: the holdings tell what the hop really gave
+            let result = result.map(|_| self.get_owned_good_qty(hop.to) - before);
+
+            let failed = result.is_err();
+            quantity = *result.as_ref().unwrap_or(&0.0);
+            hops.push(HopResult { hop: *hop, quantity_in, result });
+            if failed {
+                break;
+            }
+        }
+
+        let output = if hops.len() == plan.hops.len() && hops.iter().all(|h| h.result.is_ok()) { quantity } else { 0.0 };
+        ConversionReport { plan, hops, output }
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use market_common::good::good::Good;
+use market_common::good::good_kind::GoodKind;
+use market_common::good::good_kind::GoodKind::EUR;
+
+use crate::trader::{MarketKind, Trader};
+
+//every point of amazingness is worth this fraction of the notional, given back after each transaction
+static AMAZINGNESS_REBATE_RATE : f32 = 0.001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+//All the amounts are in EUR. Fees are paid by the trader, the rebate is paid to the trader.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TransactionCharges {
+    pub fixed_fee: f32,
+    pub proportional_fee: f32,
+    pub rebate: f32,
+}
+
+impl TransactionCharges {
+
+    pub fn fees(&self) -> f32 {
+        self.fixed_fee + self.proportional_fee
+    }
+
+    //what the transaction costs on top of (or, if negative, gives back on top of) the market price
+    pub fn net(&self) -> f32 {
+        self.fees() - self.rebate
+    }
+}
+
+//Decides what each transaction costs. `notional` is the EUR value of the transaction as quoted by the market.
+pub trait CostModel {
+    fn charges(&self, market : MarketKind, kind : GoodKind, side : TradeSide, notional : f32) -> TransactionCharges;
+}
+
+//the default: markets are the only ones taking (or giving) money
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoCosts;
+
+impl CostModel for NoCosts {
+    fn charges(&self, _ : MarketKind, _ : GoodKind, _ : TradeSide, _ : f32) -> TransactionCharges {
+        TransactionCharges::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FeeRates {
+    //EUR paid for every transaction
+    pub fixed: f32,
+    //fraction of the notional paid for every transaction
+    pub proportional: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FeeSchedule {
+    pub default_rates: FeeRates,
+    //overrides default_rates for specific markets
+    pub per_market: HashMap<MarketKind, FeeRates>,
+    //fraction of the notional given back to the trader after every transaction
+    pub rebate_rate: f32,
+}
+
+impl FeeSchedule {
+
+    pub fn new() -> Self {
+        FeeSchedule::default()
+    }
+
+    //this is what the "amazingness" of a trader is all about: free money after each transaction.
+    pub fn from_amazingness(amazingness : f32) -> Self {
+        FeeSchedule::new().with_rebate(amazingness * AMAZINGNESS_REBATE_RATE)
+    }
+
+    pub fn with_fixed_fee(mut self, fee : f32) -> Self {
+        self.default_rates.fixed = fee;
+        self
+    }
+
+    pub fn with_proportional_fee(mut self, rate : f32) -> Self {
+        self.default_rates.proportional = rate;
+        self
+    }
+
+    pub fn with_market_fees(mut self, market : MarketKind, fixed : f32, proportional : f32) -> Self {
+        self.per_market.insert(market, FeeRates { fixed, proportional });
+        self
+    }
+
+    pub fn with_rebate(mut self, rate : f32) -> Self {
+        self.rebate_rate = rate;
+        self
+    }
+
+    pub fn rates_for(&self, market : MarketKind) -> FeeRates {
+        *self.per_market.get(&market).unwrap_or(&self.default_rates)
+    }
+}
+
+impl CostModel for FeeSchedule {
+    fn charges(&self, market : MarketKind, _ : GoodKind, _ : TradeSide, notional : f32) -> TransactionCharges {
+        let rates = self.rates_for(market);
+        TransactionCharges {
+            fixed_fee: rates.fixed,
+            proportional_fee: notional * rates.proportional,
+            rebate: notional * self.rebate_rate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChargeRecord {
+    pub market: MarketKind,
+    pub kind: GoodKind,
+    pub side: TradeSide,
+    pub notional: f32,
+    pub charges: TransactionCharges,
+    //the fees the trader couldn't pay when the trade was settled (0.0 almost always: the trades check first)
+    pub shortfall: f32,
+}
+
+//every charge the trader paid or received, kept separately from the trades themselves
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ChargeLedger {
+    pub records: Vec<ChargeRecord>,
+}
+
+impl ChargeLedger {
+
+    pub fn total_fixed_fees(&self) -> f32 {
+        self.records.iter().map(|r| r.charges.fixed_fee).sum()
+    }
+
+    pub fn total_proportional_fees(&self) -> f32 {
+        self.records.iter().map(|r| r.charges.proportional_fee).sum()
+    }
+
+    pub fn total_rebates(&self) -> f32 {
+        self.records.iter().map(|r| r.charges.rebate).sum()
+    }
+
+    pub fn total_fees(&self) -> f32 {
+        self.total_fixed_fees() + self.total_proportional_fees()
+    }
+
+    pub fn net(&self) -> f32 {
+        self.total_fees() - self.total_rebates()
+    }
+
+    //the fees owed but never paid
+    pub fn total_shortfall(&self) -> f32 {
+        self.records.iter().map(|r| r.shortfall).sum()
+    }
+
+    pub fn for_market(&self, market : MarketKind) -> impl Iterator<Item = &ChargeRecord> {
+        self.records.iter().filter(move |r| r.market == market)
+    }
+}
+
+impl Trader {
+
+    pub fn with_cost_model(mut self, model : impl CostModel + 'static) -> Self {
+        self.cost_model = Box::new(model);
+        self
+    }
+
+    pub fn set_cost_model(&mut self, model : impl CostModel + 'static) {
+        self.cost_model = Box::new(model);
+    }
+
+    //installs a fee schedule with no fees and the rebate given by the trader's amazingness
+    pub fn with_amazingness_rebate(self) -> Self {
+        let amazingness = self.amazingness;
+        self.with_cost_model(FeeSchedule::from_amazingness(amazingness))
+    }
+
+    pub fn charges(&self) -> &ChargeLedger {
+        &self.charges
+    }
+
+    pub fn estimate_charges(&self, market : MarketKind, kind : GoodKind, side : TradeSide, notional : f32) -> TransactionCharges {
+        self.cost_model.charges(market, kind, side, notional)
+    }
+
+    //takes the fees from (and gives the rebate to) the trader's euros, and records them. Returns the euros charged
+    //(negative: given back). It can't fail: the trade is already done. The callers check that the trader can afford
+    //the fees before trading; if it can't anyway, it pays what it has and the rest is recorded as a shortfall.
+    pub(crate) fn settle_charges(&mut self, market : MarketKind, kind : GoodKind, side : TradeSide, notional : f32, charges : TransactionCharges) -> f32 {
+        let euros = self.owned_goods.entry(EUR).or_insert_with(|| Good::new(EUR, 0.0));
+
+        let net = charges.net();
+        let charged = if net > 0.0 {
+            let payable = net.min(euros.get_qty());
+            if payable > 0.0 && euros.split(payable).is_ok() { payable } else { 0.0 }
+        } else if net < 0.0 {
+            if euros.merge(Good::new(EUR, -net)).is_ok() { net } else { 0.0 }
+        } else {
+            0.0
+        };
+
+        self.charges.records.push(ChargeRecord { market, kind, side, notional, charges, shortfall: (net - charged).max(0.0) });
+        charged
+    }
+}
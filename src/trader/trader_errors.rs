@@ -1,4 +1,6 @@
-use market_common::market::{BuyError, LockBuyError, LockSellError, SellError};
+use market_common::market::{BuyError, LockBuyError, LockSellError, MarketGetterError, SellError};
+
+use crate::trader::trader_risk::RiskViolation;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum TraderSupplyError {
@@ -6,6 +8,14 @@ pub enum TraderSupplyError {
     GoodsNotFound,
     MarketInsufficientSupply,
     TraderInsufficientFunds,
+    //the market wanted more than the trader bid
+    PriceRejected,
+    //the market has as many open locks as it allows
+    MarketTooManyLocks,
+    //the market refused the order itself: non-positive quantity or price, unknown or expired token
+    MarketRejectedOrder,
+    //the order was stopped by the trader's own risk limits before reaching the market
+    RiskLimitBreached(RiskViolation),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -13,13 +23,54 @@ pub enum TraderDemandError {
     MarketNotFound,
     MarketInsufficientFunds,
     TraderInsufficientGoods,
+    //the trader can't pay the transaction fees
+    TraderInsufficientFunds,
+    //the market wanted to pay less than the trader offered
+    PriceRejected,
+    MarketTooManyLocks,
+    MarketRejectedOrder,
+    RiskLimitBreached(RiskViolation),
+}
+
+impl From<RiskViolation> for TraderSupplyError {
+    fn from(value: RiskViolation) -> Self {
+        TraderSupplyError::RiskLimitBreached(value)
+    }
+}
+
+impl From<RiskViolation> for TraderDemandError {
+    fn from(value: RiskViolation) -> Self {
+        TraderDemandError::RiskLimitBreached(value)
+    }
+}
+
+//The conversions below never panic: whatever a market answers, the trader gets an error it can handle.
+
+impl From<MarketGetterError> for TraderSupplyError {
+    fn from(e: MarketGetterError) -> Self {
+        match e {
+            MarketGetterError::NonPositiveQuantityAsked => TraderSupplyError::MarketRejectedOrder,
+            _ => TraderSupplyError::MarketInsufficientSupply,
+        }
+    }
+}
+
+impl From<MarketGetterError> for TraderDemandError {
+    fn from(e: MarketGetterError) -> Self {
+        match e {
+            MarketGetterError::NonPositiveQuantityAsked => TraderDemandError::MarketRejectedOrder,
+            _ => TraderDemandError::MarketInsufficientFunds,
+        }
+    }
 }
 
 impl From<LockBuyError> for TraderSupplyError {
     fn from(e: LockBuyError) -> Self {
         match e {
             LockBuyError::InsufficientGoodQuantityAvailable {..} => TraderSupplyError::MarketInsufficientSupply,
-            _ => panic!("Unhandled error: {:?}. Byebye!", e)
+            LockBuyError::BidTooLow { .. } => TraderSupplyError::PriceRejected,
+            LockBuyError::MaxAllowedLocksReached => TraderSupplyError::MarketTooManyLocks,
+            _ => TraderSupplyError::MarketRejectedOrder,
         }
     }
 }
@@ -28,7 +79,7 @@ impl From<BuyError> for TraderSupplyError {
     fn from(value: BuyError) -> Self {
         match value {
             BuyError::InsufficientGoodQuantity { .. } => TraderSupplyError::TraderInsufficientFunds,
-            _ => panic!("Unhandled error: {:?}. Byebye!", value)
+            _ => TraderSupplyError::MarketRejectedOrder,
         }
     }
 }
@@ -37,7 +88,9 @@ impl From<LockSellError> for TraderDemandError {
     fn from(e: LockSellError) -> Self {
         match e {
             LockSellError::InsufficientDefaultGoodQuantityAvailable { .. } => TraderDemandError::MarketInsufficientFunds,
-            _=> panic!("Unhandled error: {:?}. Byebye!", e)
+            LockSellError::OfferTooHigh { .. } => TraderDemandError::PriceRejected,
+            LockSellError::MaxAllowedLocksReached => TraderDemandError::MarketTooManyLocks,
+            _ => TraderDemandError::MarketRejectedOrder,
         }
     }
 }
@@ -46,16 +99,21 @@ impl From<SellError> for TraderDemandError {
     fn from(value: SellError) -> Self {
         match value {
             SellError::InsufficientGoodQuantity { .. } => TraderDemandError::TraderInsufficientGoods,
-            _ => panic!("Unhandled error: {:?}. Byebye!", value)
+            _ => TraderDemandError::MarketRejectedOrder,
         }
     }
 }
 
+//only the errors that make sense for both sides can come through here (e.g. from get_market())
 impl From<TraderSupplyError> for TraderDemandError {
     fn from(value: TraderSupplyError) -> Self {
         match value {
             TraderSupplyError::MarketNotFound => TraderDemandError::MarketNotFound,
-            _ => panic!("Something went terribly wrong: the code tried to convert a supply error into a demand error.")
+            TraderSupplyError::TraderInsufficientFunds => TraderDemandError::TraderInsufficientFunds,
+            TraderSupplyError::PriceRejected => TraderDemandError::PriceRejected,
+            TraderSupplyError::MarketTooManyLocks => TraderDemandError::MarketTooManyLocks,
+            TraderSupplyError::RiskLimitBreached(v) => TraderDemandError::RiskLimitBreached(v),
+            TraderSupplyError::GoodsNotFound | TraderSupplyError::MarketInsufficientSupply | TraderSupplyError::MarketRejectedOrder => TraderDemandError::MarketRejectedOrder,
         }
     }
-}
\ No newline at end of file
+}
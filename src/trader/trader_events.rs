@@ -0,0 +1,267 @@
+use std::cell::{Ref, RefCell};
+use std::collections::{HashSet, VecDeque};
+use std::rc::Rc;
+
+use market_common::event::event::{Event, EventKind};
+use market_common::event::notifiable::Notifiable;
+use market_common::good::good_kind::GoodKind;
+
+use crate::trader::{MarketKind, Trader, TRADER_NAME};
+
+//the feed forgets the oldest events past this size
+static DEFAULT_FEED_CAPACITY : usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObservedEventKind {
+    Bought,
+    Sold,
+    LockedBuy,
+    LockedSell,
+    Wait,
+}
+
+impl From<&EventKind> for ObservedEventKind {
+    fn from(kind : &EventKind) -> Self {
+        match kind {
+            EventKind::Bought => ObservedEventKind::Bought,
+            EventKind::Sold => ObservedEventKind::Sold,
+            EventKind::LockedBuy => ObservedEventKind::LockedBuy,
+            EventKind::LockedSell => ObservedEventKind::LockedSell,
+            EventKind::Wait => ObservedEventKind::Wait,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObservedEvent {
+    //increases by one for every event the trader observes, across all markets
+    pub sequence: usize,
+    pub market: MarketKind,
+    pub kind: ObservedEventKind,
+    pub good: GoodKind,
+    pub quantity: f32,
+    pub price: f32,
+    //who caused the event. The markets don't say: the trader only knows which events come from its own calls,
+    //everything else (None) comes from the other participants.
+    pub trader: Option<String>,
+}
+
+impl ObservedEvent {
+
+    pub fn is_trade(&self) -> bool {
+        self.kind == ObservedEventKind::Bought || self.kind == ObservedEventKind::Sold
+    }
+
+    pub fn is_own(&self) -> bool {
+        self.trader.as_deref() == Some(TRADER_NAME)
+    }
+}
+
+#[derive(Debug)]
+pub struct EventFeed {
+    events: VecDeque<ObservedEvent>,
+    capacity: usize,
+    next_sequence: usize,
+    //listeners that are not in here belong to markets the trader detached: their events are dropped
+    active_listeners: HashSet<usize>,
+    next_listener: usize,
+    //Some while the trader is calling a market: what the markets broadcast meanwhile is the trader's doing
+    acting: Option<String>,
+}
+
+impl EventFeed {
+
+    pub fn new(capacity : usize) -> Self {
+        EventFeed {
+            events: VecDeque::new(),
+            capacity,
+            next_sequence: 0,
+            active_listeners: HashSet::new(),
+            next_listener: 0,
+            acting: None,
+        }
+    }
+
+    fn record(&mut self, listener : usize, market : MarketKind, event : &Event) {
+        if !self.active_listeners.contains(&listener) {
+            return;
+        }
+        if self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(ObservedEvent {
+            sequence: self.next_sequence,
+            market,
+            kind: ObservedEventKind::from(&event.kind),
+            good: event.good_kind,
+            quantity: event.quantity,
+            price: event.price,
+            trader: self.acting.clone(),
+        });
+        self.next_sequence += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &ObservedEvent> {
+        self.events.iter()
+    }
+
+    pub fn last(&self) -> Option<&ObservedEvent> {
+        self.events.back()
+    }
+
+    //the sequence number the next event will get. Save it, and pass it to since() later to get only the new events.
+    pub fn next_sequence(&self) -> usize {
+        self.next_sequence
+    }
+
+    pub fn since(&self, sequence : usize) -> impl Iterator<Item = &ObservedEvent> {
+        self.events.iter().filter(move |e| e.sequence >= sequence)
+    }
+
+    pub fn for_market(&self, market : MarketKind) -> impl Iterator<Item = &ObservedEvent> {
+        self.events.iter().filter(move |e| e.market == market)
+    }
+
+    pub fn of_kind(&self, kind : ObservedEventKind) -> impl Iterator<Item = &ObservedEvent> {
+        self.events.iter().filter(move |e| e.kind == kind)
+    }
+
+    pub fn for_good(&self, good : GoodKind) -> impl Iterator<Item = &ObservedEvent> {
+        self.events.iter().filter(move |e| e.good == good)
+    }
+
+    //what the trader's own calls caused
+    pub fn own(&self) -> impl Iterator<Item = &ObservedEvent> {
+        self.events.iter().filter(|e| e.is_own())
+    }
+
+    //what the other participants did
+    pub fn others(&self) -> impl Iterator<Item = &ObservedEvent> {
+        self.events.iter().filter(|e| !e.is_own())
+    }
+
+    //trades of at least `min_quantity` goods by the other participants, e.g. to spot somebody moving a market
+    pub fn large_trades(&self, min_quantity : f32) -> impl Iterator<Item = &ObservedEvent> {
+        self.others().filter(move |e| e.is_trade() && e.quantity >= min_quantity)
+    }
+
+    //total quantity of `good` traded (bought + sold) in `market` among the observed events
+    pub fn traded_volume(&self, market : MarketKind, good : GoodKind) -> f32 {
+        self.for_market(market).filter(|e| e.is_trade() && e.good == good).map(|e| e.quantity).sum()
+    }
+}
+
+impl Default for EventFeed {
+    fn default() -> Self {
+        EventFeed::new(DEFAULT_FEED_CAPACITY)
+    }
+}
+
+//Marks the events broadcast while it's alive as the trader's own. Dropped at the end of the market call, even on an early return.
+pub(crate) struct OwnCall {
+    feed: Rc<RefCell<EventFeed>>,
+}
+
+impl Drop for OwnCall {
+    fn drop(&mut self) {
+        if let Ok(mut feed) = self.feed.try_borrow_mut() {
+            feed.acting = None;
+        }
+    }
+}
+
+//What actually subscribes to a market: it writes whatever the market broadcasts into the trader's feed.
+struct MarketListener {
+    id: usize,
+    market: MarketKind,
+    feed: Rc<RefCell<EventFeed>>,
+}
+
+impl Notifiable for MarketListener {
+    fn add_subscriber(&mut self, _subscriber : Box<dyn Notifiable>) {
+        //nobody listens to the listener
+    }
+
+    fn on_event(&mut self, event : Event) {
+        self.feed.borrow_mut().record(self.id, self.market, &event);
+    }
+}
+
+impl Trader {
+
+    //the trader will listen to every market it has, and to every market added later on
+    pub fn with_event_feed(mut self) -> Self {
+        self.listen_to_markets();
+        self
+    }
+
+    pub fn listen_to_markets(&mut self) {
+        self.auto_listen = true;
+        let kinds: Vec<MarketKind> = self.markets.keys().copied().collect();
+        for kind in kinds {
+            self.listen_to(kind);
+        }
+    }
+
+    //registers a listener on a single market. Does nothing if the market is unknown or already listened to.
+    pub fn listen_to(&mut self, kind : MarketKind) {
+        if self.listeners.iter().any(|(market, _)| *market == kind) {
+            return;
+        }
+        let market = match self.markets.get(&kind) {
+            Some(m) => Rc::clone(m),
+            None => return,
+        };
+
+        let id = {
+            let mut feed = self.event_feed.borrow_mut();
+            let id = feed.next_listener;
+            feed.next_listener += 1;
+            feed.active_listeners.insert(id);
+            id
+        };
+        market.borrow_mut().add_subscriber(Box::new(MarketListener { id, market: kind, feed: Rc::clone(&self.event_feed) }));
+        self.listeners.push((kind, id));
+    }
+
+    //market_common has no way to unsubscribe, so the listener stays in the market but its events are dropped
+    pub(crate) fn stop_listening_to(&mut self, kind : MarketKind) {
+        let mut feed = self.event_feed.borrow_mut();
+        self.listeners.retain(|(market, id)| {
+            if *market == kind {
+                feed.active_listeners.remove(id);
+            }
+            *market != kind
+        });
+    }
+
+    //keep the result alive around every call through which the trader trades (lock_buy, buy, lock_sell, sell)
+    pub(crate) fn own_call(&self) -> OwnCall {
+        self.event_feed.borrow_mut().acting = Some(TRADER_NAME.to_string());
+        OwnCall { feed: Rc::clone(&self.event_feed) }
+    }
+
+    pub fn listened_markets(&self) -> Vec<MarketKind> {
+        self.listeners.iter().map(|(market, _)| *market).collect()
+    }
+
+    pub fn event_feed(&self) -> Ref<'_, EventFeed> {
+        self.event_feed.borrow()
+    }
+
+    pub fn clear_event_feed(&mut self) {
+        self.event_feed.borrow_mut().clear();
+    }
+}
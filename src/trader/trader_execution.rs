@@ -0,0 +1,207 @@
+use market_common::good::good_kind::GoodKind;
+use market_common::good::good_kind::GoodKind::*;
+
+use crate::trader::{MarketKind, Trader};
+use crate::trader::trader_costs::TradeSide;
+use crate::trader::trader_orders::{OrderId, OrderOutcome};
+
+//the quantity used to read the arrival price: small enough not to move it
+static ARRIVAL_PROBE_QTY : f32 = 0.01;
+//below this quantity a slice is not worth trading
+static MIN_SLICE_QTY : f32 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExecutionStyle {
+    //the same share of what is left on each of `days` days
+    Twap { days: u32 },
+    //each day trades `participation` (0.0..=1.0) of what the market can take that day: its quantity of the good
+    //for a buy, its euros (in units of the good) for a sell. What is still unfilled after `days` days is given up.
+    Vwap { days: u32, participation: f32 },
+}
+
+impl ExecutionStyle {
+
+    pub fn days(&self) -> u32 {
+        match self {
+            ExecutionStyle::Twap { days } | ExecutionStyle::Vwap { days, .. } => *days,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Slice {
+    pub day: u32,
+    pub quantity: f32,
+    pub outcome: OrderOutcome,
+}
+
+//A large order split into slices, one per day, executed by wait()
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParentOrder {
+    pub id: OrderId,
+    pub market: MarketKind,
+    pub kind: GoodKind,
+    pub side: TradeSide,
+    pub quantity: f32,
+    pub style: ExecutionStyle,
+    pub placed_on_day: u32,
+    //the unit price quoted when the order was placed: the benchmark of the execution
+    pub arrival_price: f32,
+    pub slices: Vec<Slice>,
+}
+
+impl ParentOrder {
+
+    pub fn filled(&self) -> f32 {
+        self.slices.iter().map(|s| match s.outcome {
+            OrderOutcome::Filled { quantity, .. } => quantity,
+            _ => 0.0,
+        }).sum()
+    }
+
+    pub fn remaining(&self) -> f32 {
+        (self.quantity - self.filled()).max(0.0)
+    }
+
+    //the euros paid (buy) or received (sell) by all the slices
+    pub fn value(&self) -> f32 {
+        self.slices.iter().map(|s| match s.outcome {
+            OrderOutcome::Filled { value, .. } => value,
+            _ => 0.0,
+        }).sum()
+    }
+
+    //None until something is filled
+    pub fn average_price(&self) -> Option<f32> {
+        let filled = self.filled();
+        if filled > 0.0 { Some(self.value() / filled) } else { None }
+    }
+
+    //how much worse than the arrival price the execution was, as a fraction of it. Negative: better.
+    pub fn slippage(&self) -> Option<f32> {
+        let average = self.average_price()?;
+        if self.arrival_price <= 0.0 {
+            return None;
+        }
+        Some(match self.side {
+            TradeSide::Buy => average / self.arrival_price - 1.0,
+            TradeSide::Sell => 1.0 - average / self.arrival_price,
+        })
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.remaining() < MIN_SLICE_QTY
+    }
+
+    fn days_run(&self) -> u32 {
+        self.slices.len() as u32
+    }
+
+    fn is_over(&self) -> bool {
+        self.is_complete() || self.days_run() >= self.style.days()
+    }
+}
+
+impl Trader {
+
+    //the ids are shared with the conditional orders
+    pub fn execute_twap(&mut self, market : MarketKind, kind : GoodKind, side : TradeSide, quantity : f32, days : u32) -> OrderId {
+        self.start_execution(market, kind, side, quantity, ExecutionStyle::Twap { days })
+    }
+
+    pub fn execute_vwap(&mut self, market : MarketKind, kind : GoodKind, side : TradeSide, quantity : f32, days : u32, participation : f32) -> OrderId {
+        self.start_execution(market, kind, side, quantity, ExecutionStyle::Vwap { days, participation: participation.clamp(0.0, 1.0) })
+    }
+
+    //the first slice is traded at the next wait()
+    pub fn start_execution(&mut self, market : MarketKind, kind : GoodKind, side : TradeSide, quantity : f32, style : ExecutionStyle) -> OrderId {
+        let probe = match side {
+            TradeSide::Buy => self.try_supply_price_qt(market, kind, ARRIVAL_PROBE_QTY),
+            TradeSide::Sell => self.try_demand_price_qt(market, kind, ARRIVAL_PROBE_QTY),
+        };
+        self.next_order_id += 1;
+        let id = self.next_order_id;
+        self.executions.push(ParentOrder {
+            id,
+            market,
+            kind,
+            side,
+            quantity,
+            style,
+            placed_on_day: self.days_elapsed,
+            arrival_price: probe.map_or(0.0, |p| p / ARRIVAL_PROBE_QTY),
+            slices: Vec::new(),
+        });
+        id
+    }
+
+    pub fn cancel_execution(&mut self, id : OrderId) -> Option<ParentOrder> {
+        let index = self.executions.iter().position(|o| o.id == id)?;
+        let order = self.executions.remove(index);
+        self.finished_executions.push(order.clone());
+        Some(order)
+    }
+
+    pub fn active_executions(&self) -> &[ParentOrder] {
+        &self.executions
+    }
+
+    //the orders that are done, cancelled, or out of days
+    pub fn finished_executions(&self) -> &[ParentOrder] {
+        &self.finished_executions
+    }
+
+    pub fn execution(&self, id : OrderId) -> Option<&ParentOrder> {
+        self.executions.iter().chain(self.finished_executions.iter()).find(|o| o.id == id)
+    }
+
+    fn slice_quantity(&self, order : &ParentOrder) -> f32 {
+        let remaining = order.remaining();
+        let quantity = match order.style {
+            ExecutionStyle::Twap { days } => remaining / days.saturating_sub(order.days_run()).max(1) as f32,
+            ExecutionStyle::Vwap { participation, .. } => {
+                let liquidity = match order.side {
+                    TradeSide::Buy => self.get_good_qty(order.market, order.kind),
+                    TradeSide::Sell => self.try_demand_price_qt(order.market, order.kind, ARRIVAL_PROBE_QTY)
+                        .map_or(0.0, |p| self.get_good_qty(order.market, EUR) * ARRIVAL_PROBE_QTY / p),
+                };
+                liquidity * participation
+            }
+        };
+        quantity.min(remaining)
+    }
+
+    //buy() and sell() check the quotes themselves: a market that can't quote is just a failed slice
+    fn trade_slice(&mut self, order : &ParentOrder, quantity : f32) -> OrderOutcome {
+        match order.side {
+            TradeSide::Buy => match self.buy_measured(order.market, order.kind, quantity) {
+                Ok((bought, paid)) => OrderOutcome::Filled { quantity: bought, value: paid },
+                Err(e) => OrderOutcome::BuyFailed(e),
+            },
+            TradeSide::Sell => match self.sell_measured(order.market, order.kind, quantity) {
+                Ok((_, received)) => OrderOutcome::Filled { quantity, value: received },
+                Err(e) => OrderOutcome::SellFailed(e),
+            },
+        }
+    }
+
+    //called by wait(): one slice of every active order
+    pub(crate) fn run_executions(&mut self) {
+        for mut order in std::mem::take(&mut self.executions) {
+            let quantity = self.slice_quantity(&order);
+            let outcome = if quantity < MIN_SLICE_QTY {
+                //nothing the market can take today: the day still counts
+                OrderOutcome::Skipped
+            } else {
+                self.trade_slice(&order, quantity)
+            };
+            order.slices.push(Slice { day: self.days_elapsed, quantity, outcome });
+
+            if order.is_over() {
+                self.finished_executions.push(order);
+            } else {
+                self.executions.push(order);
+            }
+        }
+    }
+}
@@ -9,5 +9,20 @@ impl Trader {
     pub fn print_goods(&self) {
         println!(" ↳ Owned goods: {}", self.owned_goods.iter().map(|(_, t)| format!("{} {}, ", t.get_qty(), t.get_kind())).collect::<String>());
     }
+    pub fn print_charges(&self) {
+        println!("➤ Trader charges: {} transactions", self.charges.records.len());
+        println!(" ↳ Fixed fees: {}€", self.charges.total_fixed_fees());
+        println!(" ↳ Proportional fees: {}€", self.charges.total_proportional_fees());
+        println!(" ↳ Rebates: {}€", self.charges.total_rebates());
+    }
+    pub fn print_paper_report(&self) {
+        match self.paper_report() {
+            Some(report) => {
+                println!("➤ Paper trading: {} fills", report.fills.len());
+                println!(" ↳ P&L: {}€ ({}€ → {}€)", report.pnl(), report.starting_capital, report.final_capital);
+            }
+            None => println!("➤ The trader is not paper trading"),
+        }
+    }
 
 }
\ No newline at end of file
@@ -0,0 +1,213 @@
+use std::collections::HashSet;
+
+use market_common::good::good_kind::GoodKind;
+use market_common::good::good_kind::GoodKind::*;
+
+use crate::trader::{MarketKind, Trader};
+use crate::trader::trader_errors::TraderDemandError;
+
+//anything below this quantity is considered dust and is not worth a transaction
+static MIN_LIQUIDATION_QTY : f32 = 0.01;
+//every good is sold in (at least) this many slices, so that the sales get spread across the markets as their bids move
+static LIQUIDATION_SLICES : f32 = 10.0;
+//the quantity used to estimate the marginal bid of a market
+static MARGINAL_PROBE_QTY : f32 = 1.0;
+//safety net: we never want to loop forever because a market keeps answering with weird prices
+static MAX_LIQUIDATION_ROUNDS : u32 = 1000;
+
+//the order in which the goods get liquidated. Iterating the hashmap would make partial liquidations non-deterministic.
+static LIQUIDATION_ORDER : [GoodKind; 3] = [USD, YEN, YUAN];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiquidationSale {
+    pub market: MarketKind,
+    pub kind: GoodKind,
+    pub quantity: f32,
+    pub proceeds: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsoldReason {
+    //the trader does not have any market attached
+    NoMarkets,
+    //no market could afford even the smallest chunk of the good
+    NoBuyerWithBudget,
+    //the remaining quantity is too small to be sold
+    Dust,
+    //we gave up after MAX_LIQUIDATION_ROUNDS sales
+    TooManyRounds,
+    //the last market that was tried rejected the sale
+    MarketRejected(TraderDemandError),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsoldGood {
+    pub kind: GoodKind,
+    pub quantity: f32,
+    pub reason: UnsoldReason,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiquidationReport {
+    pub target_cash: Option<f32>,
+    pub cash_before: f32,
+    pub cash_after: f32,
+    pub sales: Vec<LiquidationSale>,
+    pub unsold: Vec<UnsoldGood>,
+}
+
+impl LiquidationReport {
+
+    pub fn total_proceeds(&self) -> f32 {
+        self.sales.iter().map(|s| s.proceeds).sum()
+    }
+
+    pub fn sold_qty(&self, kind : GoodKind) -> f32 {
+        self.sales.iter().filter(|s| s.kind == kind).map(|s| s.quantity).sum()
+    }
+
+    //true if a target was given and the trader now holds at least that much cash,
+    //or if no target was given and nothing was left unsold
+    pub fn is_complete(&self) -> bool {
+        match self.target_cash {
+            Some(target) => self.cash_after >= target,
+            None => self.unsold.iter().all(|u| u.reason == UnsoldReason::Dust),
+        }
+    }
+}
+
+impl Trader {
+
+    //"cashout" all the owned goods, aka sell all the goods to the markets for euros. The sales are spread over the markets by marginal bid.
+    pub fn bailout(&mut self) -> LiquidationReport {
+        self.liquidate(None)
+    }
+
+    //sell goods only until the trader owns at least `target_cash` euros.
+    pub fn liquidate_to(&mut self, target_cash : f32) -> LiquidationReport {
+        self.liquidate(Some(target_cash))
+    }
+
+    pub fn liquidate(&mut self, target_cash : Option<f32>) -> LiquidationReport {
+        let cash_before = self.get_owned_good_qty(EUR);
+        let mut report = LiquidationReport {
+            target_cash,
+            cash_before,
+            cash_after: cash_before,
+            sales: Vec::new(),
+            unsold: Vec::new(),
+        };
+
+        for kind in LIQUIDATION_ORDER {
+            if target_cash.map_or(false, |t| self.get_owned_good_qty(EUR) >= t) {
+                break;
+            }
+            if !self.owned_goods.contains_key(&kind) {
+                continue;
+            }
+            if let Some(unsold) = self.liquidate_good(kind, target_cash, &mut report.sales) {
+                report.unsold.push(unsold);
+            }
+        }
+
+        report.cash_after = self.get_owned_good_qty(EUR);
+        report
+    }
+
+    //sells `kind` chunk by chunk, always to the market with the best marginal bid. Returns what could not be sold, if anything.
+    fn liquidate_good(&mut self, kind : GoodKind, target_cash : Option<f32>, sales : &mut Vec<LiquidationSale>) -> Option<UnsoldGood> {
+
+        if self.markets.is_empty() {
+            return Some(UnsoldGood { kind, quantity: self.get_owned_good_qty(kind), reason: UnsoldReason::NoMarkets });
+        }
+
+        let slice = self.get_owned_good_qty(kind) / LIQUIDATION_SLICES;
+        //the markets that refused the current chunk. A refusal may be temporary (e.g. too many locks), so they get another chance at the next one.
+        let mut rejected_by: HashSet<MarketKind> = HashSet::new();
+        let mut last_rejection = None;
+        let mut rounds = 0;
+
+        loop {
+            let remaining = self.get_owned_good_qty(kind);
+            let cash = self.get_owned_good_qty(EUR);
+
+            if target_cash.map_or(false, |t| cash >= t) {
+                return None;
+            }
+            if remaining < MIN_LIQUIDATION_QTY {
+                return if remaining > 0.0 {
+                    Some(UnsoldGood { kind, quantity: remaining, reason: UnsoldReason::Dust })
+                } else {
+                    None
+                };
+            }
+            if rounds >= MAX_LIQUIDATION_ROUNDS {
+                return Some(UnsoldGood { kind, quantity: remaining, reason: UnsoldReason::TooManyRounds });
+            }
+            rounds += 1;
+
+            //find the market with the best marginal bid among the ones that can afford at least a minimal chunk
+            let mut best: Option<(MarketKind, f32, f32)> = None;
+            for market in self.markets.keys().copied().collect::<Vec<_>>() {
+                if rejected_by.contains(&market) {
+                    continue;
+                }
+                let capacity = self.max_affordable_sell_qty(market, kind, remaining);
+                if capacity < MIN_LIQUIDATION_QTY {
+                    continue;
+                }
+                let probe = f32::min(capacity, MARGINAL_PROBE_QTY);
+                let bid = match self.try_demand_price_qt(market, kind, probe) {
+                    Some(price) => price / probe,
+                    None => continue,
+                };
+                if best.map_or(true, |(_, best_bid, _)| bid > best_bid) {
+                    best = Some((market, bid, capacity));
+                }
+            }
+
+            let (market, _, capacity) = match best {
+                Some(b) => b,
+                //no market can afford the good, or all of them refused the same chunk
+                None => {
+                    let reason = last_rejection.map_or(UnsoldReason::NoBuyerWithBudget, UnsoldReason::MarketRejected);
+                    return Some(UnsoldGood { kind, quantity: remaining, reason });
+                }
+            };
+
+            let mut chunk = capacity.min(remaining).min(slice.max(MIN_LIQUIDATION_QTY));
+
+            //don't sell more than needed to reach the target
+            if let Some(target) = target_cash {
+                let needed = target - cash;
+                if self.try_demand_price_qt(market, kind, chunk).map_or(false, |p| p > needed) {
+                    chunk = self.min_qty_raising(market, kind, chunk, needed).max(MIN_LIQUIDATION_QTY);
+                }
+            }
+
+            match self.sell(market, kind, chunk) {
+                Ok(proceeds) => {
+                    sales.push(LiquidationSale { market, kind, quantity: chunk, proceeds });
+                    rejected_by.clear();
+                }
+                Err(e) => {
+                    rejected_by.insert(market);
+                    last_rejection = Some(e);
+                }
+            }
+        }
+    }
+
+    //like get_demand_price_qt, but returns None instead of panicking
+    pub(crate) fn try_demand_price_qt(&self, market : MarketKind, kind : GoodKind, quantity : f32) -> Option<f32> {
+        if quantity <= 0.0 {
+            return None;
+        }
+        self.markets.get(&market)?.borrow().get_sell_price(kind, quantity).ok()
+    }
+
+    //the smallest quantity (up to `upper`) whose sale yields at least `amount` euros
+    fn min_qty_raising(&self, market : MarketKind, kind : GoodKind, upper : f32, amount : f32) -> f32 {
+        Self::bisect_qty(upper, |qty| self.try_demand_price_qt(market, kind, qty).map_or(true, |p| p < amount)).1
+    }
+}
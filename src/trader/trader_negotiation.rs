@@ -0,0 +1,175 @@
+use market_common::good::good_kind::GoodKind;
+use market_common::market::{LockBuyError, LockSellError};
+
+use crate::trader::{MarketKind, Trader, TRADER_NAME};
+use crate::trader::trader_costs::TradeSide;
+use crate::trader::trader_errors::{TraderDemandError, TraderSupplyError};
+
+//How buy() and sell() haggle. The first bid is `aggressiveness` (a fraction of the quote) below the quoted price,
+//the first offer as much above it; every refusal moves the price back towards the quote, which is the last attempt.
+//With a single attempt there is no haggling: it is the quote. When a market tells how far it would go, the trader goes straight there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Negotiation {
+    pub aggressiveness: f32,
+    //the quote included
+    pub max_attempts: u32,
+}
+
+impl Negotiation {
+
+    pub fn new(aggressiveness : f32, max_attempts : u32) -> Self {
+        Negotiation { aggressiveness: aggressiveness.clamp(0.0, 1.0), max_attempts: max_attempts.max(1) }
+    }
+}
+
+impl Default for Negotiation {
+    fn default() -> Self {
+        Negotiation::new(0.05, 4)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NegotiationRecord {
+    pub day: u32,
+    pub market: MarketKind,
+    pub kind: GoodKind,
+    pub side: TradeSide,
+    pub quantity: f32,
+    //total EUR prices
+    pub quoted: f32,
+    pub agreed: f32,
+    pub attempts: u32,
+}
+
+impl NegotiationRecord {
+
+    //the euros saved (buy) or gained (sell) compared with the quote
+    pub fn savings(&self) -> f32 {
+        match self.side {
+            TradeSide::Buy => self.quoted - self.agreed,
+            TradeSide::Sell => self.agreed - self.quoted,
+        }
+    }
+}
+
+impl Trader {
+
+    pub fn with_negotiation(mut self, negotiation : Negotiation) -> Self {
+        self.set_negotiation(negotiation);
+        self
+    }
+
+    pub fn set_negotiation(&mut self, negotiation : Negotiation) {
+        self.negotiation = Some(negotiation);
+    }
+
+    //back to locking at the quoted price
+    pub fn stop_negotiating(&mut self) {
+        self.negotiation = None;
+    }
+
+    pub fn negotiation_records(&self) -> &[NegotiationRecord] {
+        &self.negotiation_records
+    }
+
+    pub fn negotiation_savings(&self) -> f32 {
+        self.negotiation_records.iter().map(|r| r.savings()).sum()
+    }
+
+    //the price of the first attempt: `aggressiveness` away from the quote, unless there is no room left to come back to it
+    fn opening_price(quoted : f32, away : f32, negotiation : Negotiation) -> f32 {
+        if negotiation.max_attempts <= 1 { quoted } else { quoted + away * negotiation.aggressiveness }
+    }
+
+    //the price of the next attempt: the market's own limit if it gave a usable one, otherwise an even step towards the quote
+    fn next_price(price : f32, quoted : f32, hint : f32, attempts_left : u32) -> f32 {
+        let between = if price < quoted { hint > price && hint <= quoted } else { hint < price && hint >= quoted };
+        if between {
+            hint
+        } else if attempts_left <= 1 {
+            quoted
+        } else {
+            price + (quoted - price) / attempts_left as f32
+        }
+    }
+
+    //locks `quantity` at the lowest total price the market accepts. Returns the token and the agreed price.
+    pub(crate) fn negotiate_lock_buy(&mut self, market : MarketKind, kind : GoodKind, quantity : f32, quoted : f32) -> Result<(String, f32), TraderSupplyError> {
+        let market_ref = self.get_market(market)?;
+        let negotiation = match self.negotiation {
+            Some(n) => n,
+            None => {
+                let _own = self.own_call();
+                let token = market_ref.borrow_mut().lock_buy(kind, quantity, quoted, TRADER_NAME.to_string())?;
+                return Ok((token, quoted));
+            }
+        };
+
+        let mut bid = Self::opening_price(quoted, -quoted, negotiation);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let result = {
+                let _own = self.own_call();
+                market_ref.borrow_mut().lock_buy(kind, quantity, bid, TRADER_NAME.to_string())
+            };
+            match result {
+                Ok(token) => {
+                    self.record_negotiation(market, kind, TradeSide::Buy, quantity, (quoted, bid), attempts);
+                    return Ok((token, bid));
+                }
+                Err(LockBuyError::BidTooLow { lowest_acceptable_bid, .. }) => {
+                    if attempts >= negotiation.max_attempts || bid >= quoted {
+                        return Err(TraderSupplyError::PriceRejected);
+                    }
+                    bid = Self::next_price(bid, quoted, lowest_acceptable_bid, negotiation.max_attempts - attempts);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    //locks the sale of `quantity` at the highest total price the market accepts. Returns the token and the agreed price.
+    pub(crate) fn negotiate_lock_sell(&mut self, market : MarketKind, kind : GoodKind, quantity : f32, quoted : f32) -> Result<(String, f32), TraderDemandError> {
+        let market_ref = self.get_market(market)?;
+        let negotiation = match self.negotiation {
+            Some(n) => n,
+            None => {
+                let _own = self.own_call();
+                let token = market_ref.borrow_mut().lock_sell(kind, quantity, quoted, TRADER_NAME.to_string())?;
+                return Ok((token, quoted));
+            }
+        };
+
+        let mut offer = Self::opening_price(quoted, quoted, negotiation);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let result = {
+                let _own = self.own_call();
+                market_ref.borrow_mut().lock_sell(kind, quantity, offer, TRADER_NAME.to_string())
+            };
+            match result {
+                Ok(token) => {
+                    self.record_negotiation(market, kind, TradeSide::Sell, quantity, (quoted, offer), attempts);
+                    return Ok((token, offer));
+                }
+                Err(LockSellError::OfferTooHigh { highest_acceptable_offer, .. }) => {
+                    if attempts >= negotiation.max_attempts || offer <= quoted {
+                        return Err(TraderDemandError::PriceRejected);
+                    }
+                    offer = Self::next_price(offer, quoted, highest_acceptable_offer, negotiation.max_attempts - attempts);
+                }
+                //asking more than the market's budget is just one more refusal
+                Err(LockSellError::InsufficientDefaultGoodQuantityAvailable { .. }) if offer > quoted && attempts < negotiation.max_attempts => {
+                    offer = quoted;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn record_negotiation(&mut self, market : MarketKind, kind : GoodKind, side : TradeSide, quantity : f32, (quoted, agreed) : (f32, f32), attempts : u32) {
+        self.negotiation_records.push(NegotiationRecord { day: self.days_elapsed, market, kind, side, quantity, quoted, agreed, attempts });
+    }
+}
@@ -0,0 +1,229 @@
+use market_common::good::good_kind::GoodKind;
+
+use crate::trader::{MarketKind, Trader};
+use crate::trader::trader_costs::TradeSide;
+use crate::trader::trader_errors::{TraderDemandError, TraderSupplyError};
+
+pub type OrderId = u64;
+
+//All the prices are unit prices in EUR, as quoted by the market for the whole quantity of the order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderTrigger {
+    //buy as soon as the market asks at most this much
+    LimitBuy { max_price: f32 },
+    //sell as soon as the market bids at least this much
+    LimitSell { min_price: f32 },
+    //sell when the bid drops to this price or below
+    StopLoss { stop_price: f32 },
+    //sell when the bid rises to this price or above
+    TakeProfit { target_price: f32 },
+    //sell when the bid drops by `trail` (as a fraction) from the best bid seen since the order was placed
+    TrailingStop { trail: f32, peak: f32 },
+}
+
+impl OrderTrigger {
+
+    pub fn side(&self) -> TradeSide {
+        match self {
+            OrderTrigger::LimitBuy { .. } => TradeSide::Buy,
+            _ => TradeSide::Sell,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderSize {
+    Quantity(f32),
+    //whatever the trader owns of the good when the order triggers
+    WholePosition,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionalOrder {
+    pub id: OrderId,
+    pub market: MarketKind,
+    pub kind: GoodKind,
+    pub size: OrderSize,
+    pub trigger: OrderTrigger,
+    pub placed_on_day: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderOutcome {
+    //the quantity traded and the euros that left (buy) or reached (sell) the trader, fees included on both sides
+    Filled { quantity: f32, value: f32 },
+    BuyFailed(TraderSupplyError),
+    SellFailed(TraderDemandError),
+    //a sell order triggered, but the trader owns none of the good
+    NothingToSell,
+    //an execution slice that wasn't traded: the market could take nothing that day
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderExecution {
+    pub order: ConditionalOrder,
+    pub day: u32,
+    //the unit price that triggered the order
+    pub trigger_price: f32,
+    pub outcome: OrderOutcome,
+}
+
+impl OrderExecution {
+
+    pub fn is_filled(&self) -> bool {
+        matches!(self.outcome, OrderOutcome::Filled { .. })
+    }
+}
+
+//Conditional orders are evaluated after every day (wait() and wait_for()) and, if enabled, after every trade.
+//An order that triggers is removed from the book whether the trade succeeds or not: the execution tells what happened.
+impl Trader {
+
+    pub fn with_orders_after_trades(mut self) -> Self {
+        self.orders_after_trades = true;
+        self
+    }
+
+    pub fn set_orders_after_trades(&mut self, enabled : bool) {
+        self.orders_after_trades = enabled;
+    }
+
+    pub fn place_limit_buy(&mut self, market : MarketKind, kind : GoodKind, quantity : f32, max_price : f32) -> OrderId {
+        self.place_order(market, kind, OrderSize::Quantity(quantity), OrderTrigger::LimitBuy { max_price })
+    }
+
+    pub fn place_limit_sell(&mut self, market : MarketKind, kind : GoodKind, quantity : f32, min_price : f32) -> OrderId {
+        self.place_order(market, kind, OrderSize::Quantity(quantity), OrderTrigger::LimitSell { min_price })
+    }
+
+    pub fn place_stop_loss(&mut self, market : MarketKind, kind : GoodKind, size : OrderSize, stop_price : f32) -> OrderId {
+        self.place_order(market, kind, size, OrderTrigger::StopLoss { stop_price })
+    }
+
+    pub fn place_take_profit(&mut self, market : MarketKind, kind : GoodKind, size : OrderSize, target_price : f32) -> OrderId {
+        self.place_order(market, kind, size, OrderTrigger::TakeProfit { target_price })
+    }
+
+    //the peak starts from the current bid, if the market gives one
+    pub fn place_trailing_stop(&mut self, market : MarketKind, kind : GoodKind, size : OrderSize, trail : f32) -> OrderId {
+        let peak = self.order_quantity(kind, size, TradeSide::Sell)
+            .and_then(|qty| self.order_unit_price(market, kind, TradeSide::Sell, qty))
+            .unwrap_or(0.0);
+        self.place_order(market, kind, size, OrderTrigger::TrailingStop { trail, peak })
+    }
+
+    pub fn place_order(&mut self, market : MarketKind, kind : GoodKind, size : OrderSize, trigger : OrderTrigger) -> OrderId {
+        self.next_order_id += 1;
+        let id = self.next_order_id;
+        self.conditional_orders.push(ConditionalOrder { id, market, kind, size, trigger, placed_on_day: self.days_elapsed });
+        id
+    }
+
+    pub fn cancel_order(&mut self, id : OrderId) -> Option<ConditionalOrder> {
+        let index = self.conditional_orders.iter().position(|o| o.id == id)?;
+        Some(self.conditional_orders.remove(index))
+    }
+
+    pub fn cancel_all_orders(&mut self) -> Vec<ConditionalOrder> {
+        std::mem::take(&mut self.conditional_orders)
+    }
+
+    pub fn open_orders(&self) -> &[ConditionalOrder] {
+        &self.conditional_orders
+    }
+
+    //every order that triggered so far, oldest first
+    pub fn order_executions(&self) -> &[OrderExecution] {
+        &self.order_executions
+    }
+
+    fn order_quantity(&self, kind : GoodKind, size : OrderSize, side : TradeSide) -> Option<f32> {
+        let qty = match (size, side) {
+            (OrderSize::Quantity(qty), _) => qty,
+            (OrderSize::WholePosition, TradeSide::Sell) => self.get_owned_good_qty(kind),
+            //buying "the whole position" doesn't mean anything
+            (OrderSize::WholePosition, TradeSide::Buy) => return None,
+        };
+        (qty > 0.0).then(|| qty)
+    }
+
+    fn order_unit_price(&self, market : MarketKind, kind : GoodKind, side : TradeSide, quantity : f32) -> Option<f32> {
+        let price = match side {
+            TradeSide::Buy => self.try_supply_price_qt(market, kind, quantity)?,
+            TradeSide::Sell => self.try_demand_price_qt(market, kind, quantity)?,
+        };
+        Some(price / quantity)
+    }
+
+    //checks every open order against the current prices and executes the ones that trigger.
+    //Returns the executions of this round, which are also added to order_executions().
+    pub fn evaluate_orders(&mut self) -> Vec<OrderExecution> {
+        //the trades below may evaluate the orders again: once is enough
+        if self.evaluating_orders {
+            return Vec::new();
+        }
+        self.evaluating_orders = true;
+
+        let mut executions = Vec::new();
+        let mut still_open = Vec::new();
+
+        for mut order in std::mem::take(&mut self.conditional_orders) {
+            let side = order.trigger.side();
+            let quantity = self.order_quantity(order.kind, order.size, side);
+            let price = quantity.and_then(|qty| self.order_unit_price(order.market, order.kind, side, qty));
+
+            let price = match price {
+                Some(p) => p,
+                None => {
+                    //nothing to sell: a stop on an empty position is simply kept for later
+                    still_open.push(order);
+                    continue;
+                }
+            };
+
+            let triggered = match &mut order.trigger {
+                OrderTrigger::LimitBuy { max_price } => price <= *max_price,
+                OrderTrigger::LimitSell { min_price } => price >= *min_price,
+                OrderTrigger::StopLoss { stop_price } => price <= *stop_price,
+                OrderTrigger::TakeProfit { target_price } => price >= *target_price,
+                OrderTrigger::TrailingStop { trail, peak } => {
+                    *peak = peak.max(price);
+                    price <= *peak * (1.0 - *trail)
+                }
+            };
+            if !triggered {
+                still_open.push(order);
+                continue;
+            }
+
+            let quantity = quantity.expect("an order triggered without a quantity");
+            let outcome = match side {
+                TradeSide::Buy => match self.buy_measured(order.market, order.kind, quantity) {
+                    Ok((bought, paid)) => OrderOutcome::Filled { quantity: bought, value: paid },
+                    Err(e) => OrderOutcome::BuyFailed(e),
+                },
+                TradeSide::Sell => match self.sell_measured(order.market, order.kind, quantity) {
+                    Ok((_, received)) => OrderOutcome::Filled { quantity, value: received },
+                    Err(TraderDemandError::TraderInsufficientGoods) if self.get_owned_good_qty(order.kind) <= 0.0 => OrderOutcome::NothingToSell,
+                    Err(e) => OrderOutcome::SellFailed(e),
+                },
+            };
+            executions.push(OrderExecution { order, day: self.days_elapsed, trigger_price: price, outcome });
+        }
+
+        //orders placed while executing (e.g. by a kill switch) are kept after the old ones
+        still_open.append(&mut self.conditional_orders);
+        self.conditional_orders = still_open;
+        self.order_executions.extend(executions.iter().cloned());
+        self.evaluating_orders = false;
+        executions
+    }
+
+    //called by buy() and sell() after a successful trade
+    pub(crate) fn evaluate_orders_after_trade(&mut self) {
+        if self.orders_after_trades && !self.conditional_orders.is_empty() {
+            self.evaluate_orders();
+        }
+    }
+}
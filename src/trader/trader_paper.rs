@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use market_common::good::good::Good;
+use market_common::good::good_kind::GoodKind;
+use market_common::good::good_kind::GoodKind::*;
+
+use crate::trader::{MarketKind, Trader};
+use crate::trader::trader_costs::{ChargeLedger, ChargeRecord, TradeSide, TransactionCharges};
+use crate::trader::trader_errors::{TraderDemandError, TraderSupplyError};
+
+//How much worse than the quote a paper fill gets. The real markets never see the paper trades,
+//so without a model buying the same good a thousand times would always cost the same.
+pub trait PriceImpact {
+    //`quoted` is the market's total price for `quantity`; `traded_before` is the quantity already paper-traded
+    //on the same market, good and side.
+    fn fill_price(&self, side : TradeSide, quoted : f32, quantity : f32, traded_before : f32) -> f32;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoImpact;
+
+impl PriceImpact for NoImpact {
+    fn fill_price(&self, _ : TradeSide, quoted : f32, _ : f32, _ : f32) -> f32 {
+        quoted
+    }
+}
+
+//every unit traded before (and during) the trade moves the price by `rate` (as a fraction) against the trader
+#[derive(Debug, Clone, Copy)]
+pub struct LinearImpact {
+    pub rate: f32,
+}
+
+impl PriceImpact for LinearImpact {
+    fn fill_price(&self, side : TradeSide, quoted : f32, quantity : f32, traded_before : f32) -> f32 {
+        let impact = self.rate * (traded_before + quantity / 2.0);
+        match side {
+            TradeSide::Buy => quoted * (1.0 + impact),
+            TradeSide::Sell => quoted * (1.0 - impact).max(0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaperFill {
+    pub market: MarketKind,
+    pub kind: GoodKind,
+    pub side: TradeSide,
+    pub quantity: f32,
+    //what the real market quoted
+    pub quoted_price: f32,
+    //what the virtual ledger settled, after the price impact
+    pub fill_price: f32,
+    pub charges: TransactionCharges,
+}
+
+pub struct PaperLedger {
+    //the virtual holdings, starting as a copy of the real ones
+    goods: HashMap<GoodKind, Good>,
+    starting_capital: f32,
+    fills: Vec<PaperFill>,
+    //the fees and rebates of the paper trades: the real ledger never sees them
+    charges: ChargeLedger,
+    volume: HashMap<(MarketKind, GoodKind, TradeSide), f32>,
+    impact: Box<dyn PriceImpact>,
+    next_token: usize,
+}
+
+impl PaperLedger {
+
+    pub(crate) fn goods(&self) -> &HashMap<GoodKind, Good> {
+        &self.goods
+    }
+
+    fn quantity(&self, kind : GoodKind) -> f32 {
+        self.goods.get(&kind).map_or(0.0, |g| g.get_qty())
+    }
+
+    //virtual goods can't run short halfway: the callers check first
+    fn adjust(&mut self, kind : GoodKind, delta : f32) {
+        let quantity = self.quantity(kind) + delta;
+        self.goods.insert(kind, Good::new(kind, quantity));
+    }
+
+    //a fill only moves the price of the next ones once it's done
+    fn record_fill(&mut self, fill : PaperFill) {
+        *self.volume.entry((fill.market, fill.kind, fill.side)).or_insert(0.0) += fill.quantity;
+        self.charges.records.push(ChargeRecord { market: fill.market, kind: fill.kind, side: fill.side, notional: fill.fill_price, charges: fill.charges, shortfall: 0.0 });
+        self.fills.push(fill);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaperReport {
+    pub fills: Vec<PaperFill>,
+    pub charges: ChargeLedger,
+    pub starting_capital: f32,
+    pub final_capital: f32,
+    //final holdings minus starting holdings, per good
+    pub position_changes: HashMap<GoodKind, f32>,
+}
+
+impl PaperReport {
+
+    pub fn pnl(&self) -> f32 {
+        self.final_capital - self.starting_capital
+    }
+}
+
+impl Trader {
+
+    pub fn with_paper_trading(mut self) -> Self {
+        self.start_paper_trading(NoImpact);
+        self
+    }
+
+    //From now on buy() and sell() ask the real markets for a price but never trade with them:
+    //the fills are settled on virtual goods, a copy of the real ones, which the trader shows until stop_paper_trading().
+    pub fn start_paper_trading(&mut self, impact : impl PriceImpact + 'static) {
+        if self.paper.is_some() {
+            return;
+        }
+        self.paper = Some(PaperLedger {
+            goods: self.owned_goods.clone(),
+            starting_capital: self.get_capital(),
+            fills: Vec::new(),
+            charges: ChargeLedger::default(),
+            volume: HashMap::new(),
+            impact: Box::new(impact),
+            next_token: 0,
+        });
+    }
+
+    //drops the virtual goods (the real ones were never touched) and reports what the paper trades would have done
+    pub fn stop_paper_trading(&mut self) -> Option<PaperReport> {
+        let report = self.paper_report()?;
+        self.paper = None;
+        Some(report)
+    }
+
+    pub fn is_paper_trading(&self) -> bool {
+        self.paper.is_some()
+    }
+
+    pub fn paper_report(&self) -> Option<PaperReport> {
+        let ledger = self.paper.as_ref()?;
+        let position_changes = ledger.goods.iter()
+            .map(|(kind, good)| (*kind, good.get_qty() - self.owned_goods.get(kind).map_or(0.0, |g| g.get_qty())))
+            .collect();
+
+        Some(PaperReport {
+            fills: ledger.fills.clone(),
+            charges: ledger.charges.clone(),
+            starting_capital: ledger.starting_capital,
+            final_capital: self.get_capital(),
+            position_changes,
+        })
+    }
+
+    //what a fill of `quantity` would cost (or yield) now. Nothing is recorded until the fill goes through.
+    fn paper_fill_price(&self, market : MarketKind, kind : GoodKind, side : TradeSide, quoted : f32, quantity : f32) -> f32 {
+        let ledger = self.paper.as_ref().expect("paper_fill_price() called while not paper trading");
+        let traded_before = ledger.volume.get(&(market, kind, side)).copied().unwrap_or(0.0);
+        ledger.impact.fill_price(side, quoted, quantity, traded_before)
+    }
+
+    pub(crate) fn paper_token(&mut self) -> String {
+        let ledger = self.paper.as_mut().expect("paper_token() called while not paper trading");
+        ledger.next_token += 1;
+        format!("PAPER-{}", ledger.next_token)
+    }
+
+    //returns the fill, as it went in the ledger
+    pub(crate) fn paper_buy(&mut self, market : MarketKind, kind : GoodKind, amount : f32, quoted : f32) -> Result<PaperFill, TraderSupplyError> {
+        let fill_price = self.paper_fill_price(market, kind, TradeSide::Buy, quoted, amount);
+        let charges = self.cost_model.charges(market, kind, TradeSide::Buy, fill_price);
+
+        let ledger = self.paper.as_mut().expect("paper_buy() called while not paper trading");
+        if ledger.quantity(EUR) < fill_price + charges.net().max(0.0) {
+            return Err(TraderSupplyError::TraderInsufficientFunds);
+        }
+        ledger.adjust(EUR, -(fill_price + charges.net()));
+        ledger.adjust(kind, amount);
+        let fill = PaperFill { market, kind, side: TradeSide::Buy, quantity: amount, quoted_price: quoted, fill_price, charges };
+        ledger.record_fill(fill.clone());
+
+        self.save_data();
+        Ok(fill)
+    }
+
+    pub(crate) fn paper_sell(&mut self, market : MarketKind, kind : GoodKind, amount : f32, quoted : f32) -> Result<PaperFill, TraderDemandError> {
+        if self.get_owned_good_qty(kind) < amount {
+            return Err(TraderDemandError::TraderInsufficientGoods);
+        }
+        //the real market would not have been able to pay
+        if self.get_market(market)?.borrow().get_budget() < quoted {
+            return Err(TraderDemandError::MarketInsufficientFunds);
+        }
+
+        let fill_price = self.paper_fill_price(market, kind, TradeSide::Sell, quoted, amount);
+        let charges = self.cost_model.charges(market, kind, TradeSide::Sell, fill_price);
+
+        let ledger = self.paper.as_mut().expect("paper_sell() called while not paper trading");
+        if ledger.quantity(EUR) + fill_price < charges.net() {
+            return Err(TraderDemandError::TraderInsufficientFunds);
+        }
+        ledger.adjust(kind, -amount);
+        ledger.adjust(EUR, fill_price - charges.net());
+        let fill = PaperFill { market, kind, side: TradeSide::Sell, quantity: amount, quoted_price: quoted, fill_price, charges };
+        ledger.record_fill(fill.clone());
+
+        self.save_data();
+        Ok(fill)
+    }
+}
@@ -0,0 +1,138 @@
+use market_common::good::good_kind::GoodKind;
+use market_common::market::Market;
+
+use crate::trader::{DEFAULT_TRANSACTION_AMOUNT, MarketKind, Trader};
+
+//What a single market would do for a given good and quantity.
+//Prices are totals in EUR for the whole quantity, exactly like the ones returned by the markets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketQuote {
+    pub market: MarketKind,
+    //what the market pays us if we sell it the quantity. None if the market refused to quote.
+    pub bid: Option<f32>,
+    //what we pay the market if we buy the quantity from it. None if the market refused to quote.
+    pub ask: Option<f32>,
+    //how much of the good the market owns
+    pub available: f32,
+    //how many euros the market owns
+    pub budget: f32,
+}
+
+impl MarketQuote {
+
+    pub fn can_supply(&self, quantity : f32) -> bool {
+        self.ask.is_some() && self.available >= quantity
+    }
+
+    pub fn can_afford(&self) -> bool {
+        self.bid.map_or(false, |bid| bid <= self.budget)
+    }
+
+    pub fn unit_bid(&self, quantity : f32) -> Option<f32> {
+        self.bid.map(|b| b / quantity)
+    }
+
+    pub fn unit_ask(&self, quantity : f32) -> Option<f32> {
+        self.ask.map(|a| a / quantity)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuoteSnapshot {
+    pub good: GoodKind,
+    pub quantity: f32,
+    //one quote per market, ordered by MarketKind so that two snapshots are easy to compare
+    pub quotes: Vec<MarketQuote>,
+    //markets that can supply the quantity, cheapest ask first
+    pub supplier_ranking: Vec<MarketKind>,
+    //markets that can pay for the quantity, highest bid first
+    pub buyer_ranking: Vec<MarketKind>,
+}
+
+impl QuoteSnapshot {
+
+    //ranks the quotes. Used by every trader flavour, whatever way it collected the quotes.
+    pub fn from_quotes(kind : GoodKind, quantity : f32, mut quotes : Vec<MarketQuote>) -> Self {
+        quotes.sort_by_key(|q| q.market);
+
+        let mut suppliers: Vec<&MarketQuote> = quotes.iter().filter(|q| q.can_supply(quantity)).collect();
+        suppliers.sort_by(|a, b| a.ask.partial_cmp(&b.ask).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut buyers: Vec<&MarketQuote> = quotes.iter().filter(|q| q.can_afford()).collect();
+        buyers.sort_by(|a, b| b.bid.partial_cmp(&a.bid).unwrap_or(std::cmp::Ordering::Equal));
+
+        QuoteSnapshot {
+            good: kind,
+            quantity,
+            supplier_ranking: suppliers.iter().map(|q| q.market).collect(),
+            buyer_ranking: buyers.iter().map(|q| q.market).collect(),
+            quotes,
+        }
+    }
+
+    //asks a single market for its quote
+    pub fn quote_market(marketkind : MarketKind, market : &dyn Market, kind : GoodKind, quantity : f32) -> MarketQuote {
+        MarketQuote {
+            market: marketkind,
+            bid: market.get_sell_price(kind, quantity).ok(),
+            ask: market.get_buy_price(kind, quantity).ok(),
+            available: market.get_goods().into_iter().find(|g| g.good_kind == kind).map_or(0.0, |g| g.quantity),
+            budget: market.get_budget(),
+        }
+    }
+
+    pub fn quote(&self, market : MarketKind) -> Option<&MarketQuote> {
+        self.quotes.iter().find(|q| q.market == market)
+    }
+
+    pub fn cheapest_supplier(&self) -> Option<MarketKind> {
+        self.supplier_ranking.first().copied()
+    }
+
+    pub fn best_buyer(&self) -> Option<MarketKind> {
+        self.buyer_ranking.first().copied()
+    }
+
+    pub fn best_ask(&self) -> Option<f32> {
+        self.cheapest_supplier().and_then(|m| self.quote(m)).and_then(|q| q.ask)
+    }
+
+    pub fn best_bid(&self) -> Option<f32> {
+        self.best_buyer().and_then(|m| self.quote(m)).and_then(|q| q.bid)
+    }
+
+    //the euros we would make by buying at the cheapest supplier and selling at the best buyer right away (can be negative)
+    pub fn round_trip_spread(&self) -> Option<f32> {
+        Some(self.best_bid()? - self.best_ask()?)
+    }
+}
+
+impl Trader {
+
+    pub fn quote_snapshot(&self, kind : GoodKind, quantity : f32) -> QuoteSnapshot {
+
+        let quotes = self.markets.iter()
+            .map(|(marketkind, market)| QuoteSnapshot::quote_market(*marketkind, &*market.borrow(), kind, quantity))
+            .collect();
+
+        QuoteSnapshot::from_quotes(kind, quantity, quotes)
+    }
+
+    //None if no market can supply the quantity
+    pub fn cheapest_supplier(&self, kind : GoodKind) -> Option<MarketKind> {
+        self.cheapest_supplier_for(kind, DEFAULT_TRANSACTION_AMOUNT)
+    }
+
+    pub fn cheapest_supplier_for(&self, kind : GoodKind, quantity : f32) -> Option<MarketKind> {
+        self.quote_snapshot(kind, quantity).cheapest_supplier()
+    }
+
+    //None if no market can afford the quantity
+    pub fn best_buyer(&self, kind : GoodKind) -> Option<MarketKind> {
+        self.best_buyer_for(kind, DEFAULT_TRANSACTION_AMOUNT)
+    }
+
+    pub fn best_buyer_for(&self, kind : GoodKind, quantity : f32) -> Option<MarketKind> {
+        self.quote_snapshot(kind, quantity).best_buyer()
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use market_common::good::good_kind::GoodKind;
+use market_common::good::good_kind::GoodKind::*;
+
+use crate::trader::{MarketKind, Trader};
+use crate::trader::trader_costs::TradeSide;
+use crate::trader::trader_errors::{TraderDemandError, TraderSupplyError};
+use crate::trader::trader_orders::OrderOutcome;
+
+//below this many euros a trade is not worth it
+static MIN_REBALANCE_EUR : f32 = 0.01;
+static REBALANCED_GOODS : [GoodKind; 3] = [USD, YEN, YUAN];
+
+//Target weights of the portfolio value. EUR takes whatever the other goods leave, unless it's given explicitly;
+//weights that add up to more than 1.0 are scaled down.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RebalancePolicy {
+    targets: HashMap<GoodKind, f32>,
+    //a good is traded only if its weight is further than this from the target (e.g. 0.05 = 5 percentage points)
+    pub tolerance: f32,
+}
+
+impl RebalancePolicy {
+
+    pub fn new(targets : &[(GoodKind, f32)], tolerance : f32) -> Self {
+        let mut weights: HashMap<GoodKind, f32> = targets.iter().map(|(k, w)| (*k, w.max(0.0))).collect();
+        let total: f32 = weights.values().sum();
+        if total > 1.0 {
+            weights.values_mut().for_each(|w| *w /= total);
+        }
+        let others: f32 = weights.iter().filter(|(k, _)| **k != EUR).map(|(_, w)| *w).sum();
+        weights.entry(EUR).or_insert((1.0 - others).max(0.0));
+        RebalancePolicy { targets: weights, tolerance: tolerance.max(0.0) }
+    }
+
+    pub fn target(&self, kind : GoodKind) -> f32 {
+        self.targets.get(&kind).copied().unwrap_or(0.0)
+    }
+}
+
+//what the holdings are worth, selling each of them whole to the best buyer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Valuation {
+    pub total: f32,
+    pub values: HashMap<GoodKind, f32>,
+}
+
+impl Valuation {
+
+    pub fn weight(&self, kind : GoodKind) -> f32 {
+        if self.total > 0.0 { self.values.get(&kind).copied().unwrap_or(0.0) / self.total } else { 0.0 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RebalanceTrade {
+    pub market: Option<MarketKind>,
+    pub kind: GoodKind,
+    pub side: TradeSide,
+    //the euros the trade was meant to raise (sell) or spend (buy)
+    pub planned_eur: f32,
+    pub outcome: OrderOutcome,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RebalanceReport {
+    pub day: u32,
+    pub before: Valuation,
+    pub trades: Vec<RebalanceTrade>,
+    pub after: Valuation,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RebalanceSchedule {
+    policy: RebalancePolicy,
+    every_days: u32,
+    last_day: Option<u32>,
+}
+
+impl Trader {
+
+    pub fn valuation(&self) -> Valuation {
+        let mut values = HashMap::new();
+        values.insert(EUR, self.get_owned_good_qty(EUR));
+        for kind in REBALANCED_GOODS {
+            let quantity = self.get_owned_good_qty(kind);
+            let value = self.markets.keys()
+                .filter_map(|market| self.sell_yield(*market, kind, quantity))
+                .fold(0.0, f32::max);
+            values.insert(kind, value);
+        }
+        Valuation { total: values.values().sum(), values }
+    }
+
+    //sells what is over weight first, to raise the cash, then buys what is under weight, biggest gap first
+    pub fn rebalance(&mut self, policy : &RebalancePolicy) -> RebalanceReport {
+        let before = self.valuation();
+        let mut trades = Vec::new();
+
+        let gap = |kind : GoodKind| policy.target(kind) * before.total - before.values.get(&kind).copied().unwrap_or(0.0);
+        let out_of_band = |kind : GoodKind| (before.weight(kind) - policy.target(kind)).abs() > policy.tolerance;
+
+        for kind in REBALANCED_GOODS {
+            let excess = -gap(kind);
+            if out_of_band(kind) && excess > MIN_REBALANCE_EUR {
+                trades.push(self.rebalance_sell(kind, excess));
+            }
+        }
+
+        let mut deficits: Vec<(GoodKind, f32)> = REBALANCED_GOODS.iter()
+            .map(|kind| (*kind, gap(*kind)))
+            .filter(|(kind, deficit)| out_of_band(*kind) && *deficit > MIN_REBALANCE_EUR)
+            .collect();
+        deficits.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        for (kind, deficit) in deficits {
+            trades.push(self.rebalance_buy(kind, deficit));
+        }
+
+        RebalanceReport { day: self.days_elapsed, before, trades, after: self.valuation() }
+    }
+
+    //to the market that needs the smallest quantity to raise `amount`. If no market can raise all of it,
+    //as much as possible to the market that pays the most for what it can afford.
+    fn rebalance_sell(&mut self, kind : GoodKind, amount : f32) -> RebalanceTrade {
+        let markets: Vec<MarketKind> = self.markets.keys().copied().collect();
+        let whole = markets.iter()
+            .filter_map(|market| Some((*market, self.sell_qty_for_amount(*market, kind, amount)?)))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        let best = whole.or_else(|| {
+            let owned = self.get_owned_good_qty(kind);
+            markets.iter()
+                .map(|market| (*market, self.max_affordable_sell_qty(*market, kind, owned)))
+                .filter(|(_, quantity)| *quantity > 0.0)
+                .filter_map(|(market, quantity)| Some((market, quantity, self.sell_yield(market, kind, quantity)?)))
+                .max_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal))
+                .map(|(market, quantity, _)| (market, quantity))
+        });
+
+        let (market, outcome) = match best {
+            Some((market, quantity)) => (Some(market), match self.sell_measured(market, kind, quantity) {
+                Ok((_, received)) => OrderOutcome::Filled { quantity, value: received },
+                Err(e) => OrderOutcome::SellFailed(e),
+            }),
+            None => (None, OrderOutcome::SellFailed(TraderDemandError::MarketInsufficientFunds)),
+        };
+        RebalanceTrade { market, kind, side: TradeSide::Sell, planned_eur: amount, outcome }
+    }
+
+    //to the market that gives the most for the money
+    fn rebalance_buy(&mut self, kind : GoodKind, amount : f32) -> RebalanceTrade {
+        let budget = amount.min(self.get_owned_good_qty(EUR));
+        let best = self.markets.keys()
+            .map(|market| (*market, self.max_buy_qty_for_budget(*market, kind, budget)))
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        let (market, outcome) = match best {
+            Some((market, _)) => (Some(market), match self.buy_with_budget_measured(market, kind, budget) {
+                Ok((quantity, paid)) => OrderOutcome::Filled { quantity, value: paid },
+                Err(e) => OrderOutcome::BuyFailed(e),
+            }),
+            None => (None, OrderOutcome::BuyFailed(TraderSupplyError::MarketNotFound)),
+        };
+        RebalanceTrade { market, kind, side: TradeSide::Buy, planned_eur: amount, outcome }
+    }
+
+    //rebalance inside run()/run_until(), after an iteration, whenever `every_days` days have passed since the last time
+    pub fn with_rebalance_schedule(mut self, policy : RebalancePolicy, every_days : u32) -> Self {
+        self.set_rebalance_schedule(policy, every_days);
+        self
+    }
+
+    pub fn set_rebalance_schedule(&mut self, policy : RebalancePolicy, every_days : u32) {
+        self.rebalance_schedule = Some(RebalanceSchedule { policy, every_days: every_days.max(1), last_day: None });
+    }
+
+    pub fn clear_rebalance_schedule(&mut self) {
+        self.rebalance_schedule = None;
+    }
+
+    //the reports of the scheduled rebalances
+    pub fn rebalance_reports(&self) -> &[RebalanceReport] {
+        &self.rebalance_reports
+    }
+
+    pub(crate) fn run_scheduled_rebalance(&mut self) {
+        let day = self.days_elapsed;
+        let policy = match &self.rebalance_schedule {
+            Some(schedule) if schedule.last_day.map_or(true, |last| day >= last + schedule.every_days) => schedule.policy.clone(),
+            _ => return,
+        };
+        let report = self.rebalance(&policy);
+        self.rebalance_reports.push(report);
+        if let Some(schedule) = self.rebalance_schedule.as_mut() {
+            schedule.last_day = Some(day);
+        }
+    }
+}
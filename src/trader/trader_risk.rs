@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use market_common::good::good_kind::GoodKind;
+use market_common::good::good_kind::GoodKind::EUR;
+
+use crate::trader::{MarketKind, Trader};
+use crate::trader::trader_costs::TradeSide;
+use crate::trader::trader_liquidation::LiquidationReport;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RiskViolation {
+    //the order would make the position in a good larger than allowed
+    MaxPosition,
+    //the order is worth more euros than allowed
+    MaxOrderNotional,
+    //the trader already lost more than allowed since the start of the day
+    MaxDailyLoss,
+    //too much of the capital would be spent in a single market
+    MaxMarketShare,
+    //the order would leave the trader with less cash than the reserve
+    MinCashReserve,
+    //the kill switch was tripped: no more trading until reset_kill_switch()
+    KillSwitchEngaged,
+}
+
+//Every limit is optional. Notionals and losses are in EUR, shares are fractions of the capital (0.0..=1.0).
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RiskLimits {
+    pub max_position: HashMap<GoodKind, f32>,
+    pub max_order_notional: Option<f32>,
+    pub max_daily_loss: Option<f32>,
+    pub max_market_share: Option<f32>,
+    pub min_cash_reserve: Option<f32>,
+    //on any breach, stop trading and sell everything
+    pub kill_switch: bool,
+}
+
+impl RiskLimits {
+
+    pub fn new() -> Self {
+        RiskLimits::default()
+    }
+
+    pub fn with_max_position(mut self, kind : GoodKind, quantity : f32) -> Self {
+        self.max_position.insert(kind, quantity);
+        self
+    }
+
+    pub fn with_max_order_notional(mut self, notional : f32) -> Self {
+        self.max_order_notional = Some(notional);
+        self
+    }
+
+    pub fn with_max_daily_loss(mut self, loss : f32) -> Self {
+        self.max_daily_loss = Some(loss);
+        self
+    }
+
+    pub fn with_max_market_share(mut self, share : f32) -> Self {
+        self.max_market_share = Some(share);
+        self
+    }
+
+    pub fn with_min_cash_reserve(mut self, reserve : f32) -> Self {
+        self.min_cash_reserve = Some(reserve);
+        self
+    }
+
+    pub fn with_kill_switch(mut self) -> Self {
+        self.kill_switch = true;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RiskBreach {
+    pub violation: RiskViolation,
+    pub market: MarketKind,
+    pub kind: GoodKind,
+    pub side: TradeSide,
+    pub quantity: f32,
+    pub notional: f32,
+    pub day: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RiskManager {
+    pub limits: RiskLimits,
+    day_start_capital: f32,
+    //EUR spent in each market, minus what was sold back there
+    market_exposure: HashMap<MarketKind, f32>,
+    kill_switch_engaged: bool,
+    //true while the kill switch is flattening the positions: those sales must go through
+    flattening: bool,
+    breaches: Vec<RiskBreach>,
+}
+
+impl Trader {
+
+    pub fn with_risk_limits(mut self, limits : RiskLimits) -> Self {
+        self.set_risk_limits(limits);
+        self
+    }
+
+    pub fn set_risk_limits(&mut self, limits : RiskLimits) {
+        self.risk = Some(RiskManager {
+            limits,
+            day_start_capital: self.get_capital(),
+            market_exposure: HashMap::new(),
+            kill_switch_engaged: false,
+            flattening: false,
+            breaches: Vec::new(),
+        });
+    }
+
+    pub fn risk_breaches(&self) -> &[RiskBreach] {
+        self.risk.as_ref().map_or(&[], |r| r.breaches.as_slice())
+    }
+
+    //the euros put into `market` and not taken out yet, as the risk limits see them (0.0 without limits)
+    pub fn market_exposure(&self, market : MarketKind) -> f32 {
+        self.risk.as_ref().and_then(|r| r.market_exposure.get(&market).copied()).unwrap_or(0.0)
+    }
+
+    pub fn is_kill_switch_engaged(&self) -> bool {
+        self.risk.as_ref().map_or(false, |r| r.kill_switch_engaged)
+    }
+
+    pub fn reset_kill_switch(&mut self) {
+        if let Some(risk) = self.risk.as_mut() {
+            risk.kill_switch_engaged = false;
+        }
+    }
+
+    //stops any further trading and sells everything for euros
+    pub fn trip_kill_switch(&mut self) -> LiquidationReport {
+        if let Some(risk) = self.risk.as_mut() {
+            risk.kill_switch_engaged = true;
+            risk.flattening = true;
+        }
+        let report = self.bailout();
+        if let Some(risk) = self.risk.as_mut() {
+            risk.flattening = false;
+        }
+        report
+    }
+
+    //called by buy(), sell() and the lock functions before touching the market
+    pub(crate) fn check_risk(&mut self, side : TradeSide, market : MarketKind, kind : GoodKind, quantity : f32, notional : f32) -> Result<(), RiskViolation> {
+        let violation = match self.find_risk_violation(side, market, kind, quantity, notional) {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let day = self.days_elapsed;
+        let risk = self.risk.as_mut().expect("a risk violation without a risk manager");
+        risk.breaches.push(RiskBreach { violation, market, kind, side, quantity, notional, day });
+
+        if risk.limits.kill_switch && !risk.kill_switch_engaged {
+            self.trip_kill_switch();
+        }
+        Err(violation)
+    }
+
+    fn find_risk_violation(&self, side : TradeSide, market : MarketKind, kind : GoodKind, quantity : f32, notional : f32) -> Option<RiskViolation> {
+        let risk = self.risk.as_ref()?;
+        if risk.flattening {
+            return None;
+        }
+        if risk.kill_switch_engaged {
+            return Some(RiskViolation::KillSwitchEngaged);
+        }
+
+        let limits = &risk.limits;
+        if limits.max_order_notional.map_or(false, |max| notional > max) {
+            return Some(RiskViolation::MaxOrderNotional);
+        }
+
+        //selling only reduces the risk: the remaining limits are about buying
+        if side == TradeSide::Sell {
+            return None;
+        }
+
+        let capital = self.get_capital();
+        if limits.max_daily_loss.map_or(false, |max| risk.day_start_capital - capital > max) {
+            return Some(RiskViolation::MaxDailyLoss);
+        }
+        if limits.max_position.get(&kind).map_or(false, |max| self.get_owned_good_qty(kind) + quantity > *max) {
+            return Some(RiskViolation::MaxPosition);
+        }
+        if limits.min_cash_reserve.map_or(false, |reserve| self.get_owned_good_qty(EUR) - notional < reserve) {
+            return Some(RiskViolation::MinCashReserve);
+        }
+        let exposure = risk.market_exposure.get(&market).copied().unwrap_or(0.0) + notional;
+        if limits.max_market_share.map_or(false, |share| capital > 0.0 && exposure / capital > share) {
+            return Some(RiskViolation::MaxMarketShare);
+        }
+        None
+    }
+
+    //called after every fill to keep the per-market exposure up to date
+    pub(crate) fn record_risk_fill(&mut self, side : TradeSide, market : MarketKind, notional : f32) {
+        if let Some(risk) = self.risk.as_mut() {
+            let exposure = risk.market_exposure.entry(market).or_insert(0.0);
+            *exposure = match side {
+                TradeSide::Buy => *exposure + notional,
+                TradeSide::Sell => (*exposure - notional).max(0.0),
+            };
+        }
+    }
+
+    //called by wait(): the daily loss is measured from the capital at the start of each day
+    pub(crate) fn start_risk_day(&mut self) {
+        let capital = self.get_capital();
+        if let Some(risk) = self.risk.as_mut() {
+            risk.day_start_capital = capital;
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use market_common::good::good_kind::GoodKind;
+
+use crate::trader::Trader;
+
+//When run_until() should stop. The conditions are checked after every iteration.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopCondition {
+    //after this many iterations of the strategy (a call made again because the strategy replaced itself counts too)
+    MaxIterations(u32),
+    //as soon as the capital reaches this value
+    CapitalAtLeast(f32),
+    //as soon as the capital drops below this value
+    CapitalBelow(f32),
+    //after this many simulated days (calls to wait()) since the run started.
+    //A strategy that never waits never gets there: add a MaxIterations with or()
+    Days(u32),
+    //when the strategy calls signal_done()
+    StrategyDone,
+    //when the strategy calls report_error()
+    OnError,
+    //stops as soon as any of the conditions holds
+    Any(Vec<StopCondition>),
+    //stops only when all of the conditions hold
+    All(Vec<StopCondition>),
+    //(an Any or All without conditions stops the run before it starts, with StopReason::NoCondition)
+}
+
+impl StopCondition {
+
+    pub fn or(self, other : StopCondition) -> StopCondition {
+        match self {
+            StopCondition::Any(mut conditions) => {
+                conditions.push(other);
+                StopCondition::Any(conditions)
+            }
+            _ => StopCondition::Any(vec![self, other]),
+        }
+    }
+
+    pub fn and(self, other : StopCondition) -> StopCondition {
+        match self {
+            StopCondition::All(mut conditions) => {
+                conditions.push(other);
+                StopCondition::All(conditions)
+            }
+            _ => StopCondition::All(vec![self, other]),
+        }
+    }
+
+    fn check(&self, state : &RunState) -> Option<StopReason> {
+        match self {
+            StopCondition::MaxIterations(n) => (state.iterations >= *n).then(|| StopReason::MaxIterations),
+            StopCondition::CapitalAtLeast(target) => (state.capital >= *target).then(|| StopReason::CapitalTarget(*target)),
+            StopCondition::CapitalBelow(floor) => (state.capital < *floor).then(|| StopReason::CapitalFloor(*floor)),
+            StopCondition::Days(days) => (state.days >= *days).then(|| StopReason::Days),
+            StopCondition::StrategyDone => state.done.then(|| StopReason::StrategyDone),
+            StopCondition::OnError => state.last_error.map(|e| StopReason::Error(e.to_string())),
+            StopCondition::Any(conditions) | StopCondition::All(conditions) if conditions.is_empty() => Some(StopReason::NoCondition),
+            StopCondition::Any(conditions) => conditions.iter().find_map(|c| c.check(state)),
+            StopCondition::All(conditions) => {
+                let reasons: Option<Vec<StopReason>> = conditions.iter().map(|c| c.check(state)).collect();
+                reasons.filter(|r| !r.is_empty()).map(StopReason::AllOf)
+            }
+        }
+    }
+}
+
+//What run_until() does when the strategy panics. The strategy is given back to the trader in any case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailurePolicy {
+    //count the iteration as done and go on with the next one
+    Skip,
+    //run the same iteration again, up to max_attempts more times, then abort
+    Retry { max_attempts: u32 },
+    //stop the run, reporting StopReason::StrategyPanicked
+    Abort,
+}
+
+impl Default for FailurePolicy {
+    fn default() -> Self {
+        FailurePolicy::Abort
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrategyFailure {
+    //the iteration of the run in which the strategy panicked
+    pub iteration: u32,
+    //0 for the first try, then 1, 2... for the retries
+    pub attempt: u32,
+    pub day: u32,
+    pub capital: f32,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    MaxIterations,
+    StrategyPanicked(String),
+    CapitalTarget(f32),
+    CapitalFloor(f32),
+    Days,
+    StrategyDone,
+    Error(String),
+    AllOf(Vec<StopReason>),
+    //the condition was an empty Any or All: there was nothing to run until
+    NoCondition,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunResult {
+    pub reason: StopReason,
+    pub iterations: u32,
+    pub days: u32,
+    pub starting_capital: f32,
+    pub final_capital: f32,
+    //the capital after every iteration
+    pub capital_history: Vec<f32>,
+    pub final_goods: HashMap<GoodKind, f32>,
+    //everything the strategy reported with report_error() during the run
+    pub errors: Vec<String>,
+    //every panic of the strategy during the run, contained by run_until()
+    pub failures: Vec<StrategyFailure>,
+}
+
+impl RunResult {
+
+    pub fn pnl(&self) -> f32 {
+        self.final_capital - self.starting_capital
+    }
+
+    //the largest drop from a peak, as a fraction of the peak (0.0 = never lost anything)
+    pub fn max_drawdown(&self) -> f32 {
+        let mut peak = self.starting_capital;
+        let mut drawdown: f32 = 0.0;
+        for capital in self.capital_history.iter() {
+            peak = peak.max(*capital);
+            if peak > 0.0 {
+                drawdown = drawdown.max((peak - capital) / peak);
+            }
+        }
+        drawdown
+    }
+}
+
+struct RunState<'a> {
+    iterations: u32,
+    days: u32,
+    capital: f32,
+    done: bool,
+    last_error: Option<&'a str>,
+}
+
+pub(crate) fn panic_message(payload : &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "panicked without a message".to_string()
+    }
+}
+
+impl Trader {
+
+    pub fn with_failure_policy(mut self, policy : FailurePolicy) -> Self {
+        self.failure_policy = policy;
+        self
+    }
+
+    pub fn set_failure_policy(&mut self, policy : FailurePolicy) {
+        self.failure_policy = policy;
+    }
+
+    //every strategy panic contained so far, across all runs
+    pub fn strategy_failures(&self) -> &[StrategyFailure] {
+        &self.strategy_failures
+    }
+
+    //the strategy calls this to tell run_until() it has nothing left to do
+    pub fn signal_done(&mut self) {
+        self.strategy_done = true;
+    }
+
+    //the strategy calls this to report a problem. The run stops on it only with StopCondition::OnError.
+    pub fn report_error(&mut self, error : impl Into<String>) {
+        self.run_errors.push(error.into());
+    }
+
+    pub fn days_elapsed(&self) -> u32 {
+        self.days_elapsed
+    }
+
+    pub fn run_until(&mut self, condition : StopCondition) -> RunResult {
+
+        let starting_capital = self.get_capital();
+        let starting_day = self.days_elapsed;
+        let first_error = self.run_errors.len();
+        let first_failure = self.strategy_failures.len();
+        let mut attempt = 0;
+        let mut capital_history = Vec::new();
+        let mut iterations = 0;
+        self.strategy_done = false;
+
+        let reason = loop {
+
+            let state = RunState {
+                iterations,
+                days: self.days_elapsed - starting_day,
+                capital: self.get_capital(),
+                done: self.strategy_done,
+                last_error: self.run_errors[first_error..].last().map(|e| e.as_str()),
+            };
+            //a run always gets at least one look at the conditions before doing anything
+            if let Some(reason) = condition.check(&state) {
+                break reason;
+            }
+
+            //set_strategy() outside of the run must not be mistaken for a change made by the strategy itself
+            self.closure_just_modified = false;
+
+            //steal the struct's operation and replace it with a temporary value:
+            let closure = std::mem::replace(&mut self.closure, Box::new(|_| { println!("the closure was replaced but not given back")}));
+            let outcome = catch_unwind(AssertUnwindSafe(|| closure(self)));
+
+            if let Err(payload) = outcome {
+                //give the strategy back, unless it replaced itself before panicking
+                if self.closure_just_modified {
+                    self.closure_just_modified = false;
+                } else {
+                    self.closure = closure;
+                }
+
+                let message = panic_message(&*payload);
+                self.strategy_failures.push(StrategyFailure {
+                    iteration: iterations,
+                    attempt,
+                    day: self.days_elapsed,
+                    capital: self.get_capital(),
+                    message: message.clone(),
+                });
+
+                match self.failure_policy {
+                    FailurePolicy::Skip => {
+                        attempt = 0;
+                        iterations += 1;
+                        capital_history.push(self.get_capital());
+                        continue;
+                    }
+                    FailurePolicy::Retry { max_attempts } if attempt < max_attempts => {
+                        attempt += 1;
+                        continue;
+                    }
+                    _ => break StopReason::StrategyPanicked(message),
+                }
+            }
+            attempt = 0;
+
+            if self.closure_just_modified {
+                self.closure_just_modified = false;
+                //since the closure was modified, it means that the trader got in a new state. Therefore, we have to call the closure again.
+                //The new closure is already in place: the old one is just dropped. The call still counts as an iteration,
+                //or a strategy that replaces itself every time would never meet MaxIterations.
+            } else {
+                self.closure = closure;
+            }
+
+            self.run_scheduled_rebalance();
+            iterations += 1;
+            capital_history.push(self.get_capital());
+        };
+
+        RunResult {
+            reason,
+            iterations,
+            days: self.days_elapsed - starting_day,
+            starting_capital,
+            final_capital: self.get_capital(),
+            capital_history,
+            final_goods: self.holdings().iter().map(|(kind, good)| (*kind, good.get_qty())).collect(),
+            errors: self.run_errors[first_error..].to_vec(),
+            failures: self.strategy_failures[first_failure..].to_vec(),
+        }
+    }
+}
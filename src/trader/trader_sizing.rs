@@ -0,0 +1,137 @@
+use market_common::good::good_kind::GoodKind;
+use market_common::good::good_kind::GoodKind::EUR;
+
+use crate::trader::{MarketKind, Trader};
+use crate::trader::trader_costs::TradeSide;
+use crate::trader::trader_errors::{TraderDemandError, TraderSupplyError};
+
+//below this quantity an order is not worth placing
+static MIN_ORDER_QTY : f32 = 0.01;
+static SIZING_SEARCH_STEPS : u32 = 40;
+//how many quantities max_buy_qty_under_avg_price() tries before bisecting
+static SIZING_GRID_POINTS : u32 = 20;
+
+//The solvers search the market's price curve by bisection, so they assume that the total price grows with the quantity,
+//which holds for every market we have. Fees from the trader's cost model are taken into account: they keep the totals
+//growing, but a fixed fee makes the average price fall and then rise with the quantity (see max_buy_qty_under_avg_price()).
+impl Trader {
+
+    //like get_supply_price_qt, but returns None instead of an error
+    pub(crate) fn try_supply_price_qt(&self, market : MarketKind, kind : GoodKind, quantity : f32) -> Option<f32> {
+        if quantity <= 0.0 {
+            return None;
+        }
+        self.markets.get(&market)?.borrow().get_buy_price(kind, quantity).ok()
+    }
+
+    //what the trader needs to have to buy `quantity`: the market price plus the fees. The rebate comes after the purchase.
+    fn buy_cost(&self, market : MarketKind, kind : GoodKind, quantity : f32) -> Option<f32> {
+        let price = self.try_supply_price_qt(market, kind, quantity)?;
+        Some(price + self.estimate_charges(market, kind, TradeSide::Buy, price).net().max(0.0))
+    }
+
+    //what selling `quantity` really yields, net of fees. None if the market can't afford it.
+    pub(crate) fn sell_yield(&self, market : MarketKind, kind : GoodKind, quantity : f32) -> Option<f32> {
+        let price = self.try_demand_price_qt(market, kind, quantity)?;
+        if price > self.get_market(market).ok()?.borrow().get_budget() {
+            return None;
+        }
+        Some(price - self.estimate_charges(market, kind, TradeSide::Sell, price).net())
+    }
+
+    //The one bisection of the trader: `ok` has to hold up to some quantity in [0, upper] and not after it.
+    //Returns the quantities right below and right above that point, or (upper, upper) if ok holds everywhere.
+    pub(crate) fn bisect_qty(upper : f32, ok : impl Fn(f32) -> bool) -> (f32, f32) {
+        if ok(upper) {
+            return (upper, upper);
+        }
+        let (mut low, mut high) = (0.0, upper);
+        for _ in 0..SIZING_SEARCH_STEPS {
+            let mid = (low + high) / 2.0;
+            if ok(mid) {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        (low, high)
+    }
+
+    //largest x in [0, upper] for which `ok(x)` holds
+    fn largest_qty(upper : f32, ok : impl Fn(f32) -> bool) -> f32 {
+        Self::bisect_qty(upper, ok).0
+    }
+
+    //the largest quantity (up to `upper`) that the market can pay for with its current budget
+    pub(crate) fn max_affordable_sell_qty(&self, market : MarketKind, kind : GoodKind, upper : f32) -> f32 {
+        let budget = match self.markets.get(&market) {
+            Some(m) => m.borrow().get_budget(),
+            None => return 0.0,
+        };
+        Self::largest_qty(upper, |qty| self.try_demand_price_qt(market, kind, qty).map_or(false, |p| p <= budget))
+    }
+
+    //the largest quantity of `kind` that `budget` euros can buy in `market`
+    pub fn max_buy_qty_for_budget(&self, market : MarketKind, kind : GoodKind, budget : f32) -> f32 {
+        let available = self.get_good_qty(market, kind);
+        Self::largest_qty(available, |qty| self.buy_cost(market, kind, qty).map_or(false, |cost| cost <= budget))
+    }
+
+    //the largest quantity of `kind` that `market` sells at an average price of at most `max_unit_price` euros per unit.
+    //With a fixed fee small orders are dear, so the average price first falls with the quantity and then rises:
+    //the price is fine only on a middle range. The grid looks for a quantity inside it, then the bisection finds where it ends.
+    pub fn max_buy_qty_under_avg_price(&self, market : MarketKind, kind : GoodKind, max_unit_price : f32) -> f32 {
+        let available = self.get_good_qty(market, kind);
+        let ok = |qty : f32| self.buy_cost(market, kind, qty).map_or(false, |cost| cost / qty <= max_unit_price);
+        let start = (1..=SIZING_GRID_POINTS).rev()
+            .map(|i| available * i as f32 / SIZING_GRID_POINTS as f32)
+            .find(|qty| ok(*qty));
+        match start {
+            Some(start) => start + Self::largest_qty(available - start, |extra| ok(start + extra)),
+            //too thin a range for the grid, if there is one at all
+            None => 0.0,
+        }
+    }
+
+    //the smallest quantity of `kind` to sell in `market` to raise `amount` euros, if the trader owns enough of it
+    //and the market can pay for it
+    pub fn sell_qty_for_amount(&self, market : MarketKind, kind : GoodKind, amount : f32) -> Option<f32> {
+        let upper = self.max_affordable_sell_qty(market, kind, self.get_owned_good_qty(kind));
+        if self.sell_yield(market, kind, upper).map_or(true, |y| y < amount) {
+            return None;
+        }
+        Some(Self::bisect_qty(upper, |qty| self.sell_yield(market, kind, qty).map_or(true, |y| y < amount)).1)
+    }
+
+    //spends at most `budget` euros (fees included) on `kind`. Returns the quantity bought.
+    pub fn buy_with_budget(&mut self, market : MarketKind, kind : GoodKind, budget : f32) -> Result<f32, TraderSupplyError> {
+        self.buy_with_budget_measured(market, kind, budget).map(|(bought, _)| bought)
+    }
+
+    //buy_with_budget() that also tells the euros it really spent (see buy_measured())
+    pub(crate) fn buy_with_budget_measured(&mut self, market : MarketKind, kind : GoodKind, budget : f32) -> Result<(f32, f32), TraderSupplyError> {
+        self.get_market(market)?;
+        let budget = budget.min(self.get_owned_good_qty(EUR));
+        let qty = self.max_buy_qty_for_budget(market, kind, budget);
+        if qty < MIN_ORDER_QTY {
+            return Err(TraderSupplyError::TraderInsufficientFunds);
+        }
+        self.buy_measured(market, kind, qty)
+    }
+
+    //sells just enough `kind` to raise `amount` euros (net of fees). Returns the euros received from the market.
+    pub fn sell_for_amount(&mut self, market : MarketKind, kind : GoodKind, amount : f32) -> Result<f32, TraderDemandError> {
+        self.get_market(market)?;
+        let qty = match self.sell_qty_for_amount(market, kind, amount) {
+            Some(qty) => qty,
+            None => {
+                //would everything the trader owns raise enough, if only the market could pay for it?
+                let owned = self.get_owned_good_qty(kind);
+                let enough_goods = self.try_demand_price_qt(market, kind, owned)
+                    .map_or(false, |p| p - self.estimate_charges(market, kind, TradeSide::Sell, p).net() >= amount);
+                return Err(if enough_goods { TraderDemandError::MarketInsufficientFunds } else { TraderDemandError::TraderInsufficientGoods });
+            }
+        };
+        self.sell(market, kind, qty.max(MIN_ORDER_QTY))
+    }
+}
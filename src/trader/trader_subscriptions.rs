@@ -0,0 +1,90 @@
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+
+use market_common::event::event::Event;
+use market_common::event::notifiable::Notifiable;
+use market_common::market::Market;
+
+use crate::trader::{MarketKind, Trader};
+
+//A subscription is a (publisher, subscriber) pair: the subscriber gets notified of the publisher's events.
+pub type Subscription = (MarketKind, MarketKind);
+
+//What the publisher gets as a subscriber, instead of the other market itself. market_common has no way to unsubscribe,
+//so remove_market() switches the relay off. It only holds a Weak: a detached market isn't kept alive by its former peers.
+struct MarketRelay {
+    target: Weak<RefCell<dyn Market>>,
+    active: Rc<Cell<bool>>,
+}
+
+impl Notifiable for MarketRelay {
+    //a relay has nobody to notify but its target
+    fn add_subscriber(&mut self, _subscriber : Box<dyn Notifiable>) {}
+
+    fn on_event(&mut self, event : Event) {
+        if !self.active.get() {
+            return;
+        }
+        if let Some(target) = self.target.upgrade() {
+            target.borrow_mut().on_event(event);
+        }
+    }
+}
+
+impl Trader {
+
+    //attaches the market and subscribes it to every other attached market (and vice versa).
+    //If a market of the same kind was already attached, it gets replaced.
+    pub fn add_market(&mut self, kind : MarketKind, market : Rc<RefCell<dyn Market>>) {
+        if self.markets.contains_key(&kind) {
+            self.remove_market(kind);
+        }
+
+        self.initialize_data(kind);
+
+        let others: Vec<(MarketKind, Rc<RefCell<dyn Market>>)> = self.markets.iter()
+            .map(|(k, m)| (*k, Rc::clone(m)))
+            .collect();
+        for (other_kind, other) in others {
+            self.subscribe(kind, &market, other_kind, &other);
+            self.subscribe(other_kind, &other, kind, &market);
+        }
+
+        self.markets.insert(kind, market);
+
+        if self.auto_listen {
+            self.listen_to(kind);
+        }
+    }
+
+    fn subscribe(&mut self, publisher_kind : MarketKind, publisher : &Rc<RefCell<dyn Market>>, subscriber_kind : MarketKind, subscriber : &Rc<RefCell<dyn Market>>) {
+        let active = Rc::new(Cell::new(true));
+        publisher.borrow_mut().add_subscriber(Box::new(MarketRelay { target: Rc::downgrade(subscriber), active: Rc::clone(&active) }));
+        self.subscriptions.insert((publisher_kind, subscriber_kind), active);
+    }
+
+    //detaches the market: it stops notifying the attached markets and they stop notifying it.
+    //Its visualizer data goes with it.
+    pub fn remove_market(&mut self, kind : MarketKind) -> Option<Rc<RefCell<dyn Market>>> {
+        self.subscriptions.retain(|(publisher, subscriber), active| {
+            let involved = *publisher == kind || *subscriber == kind;
+            if involved {
+                active.set(false);
+            }
+            !involved
+        });
+        self.stop_listening_to(kind);
+        self.remove_data(kind);
+        self.markets.remove(&kind)
+    }
+
+    pub fn subscriptions(&self) -> Vec<Subscription> {
+        let mut subscriptions: Vec<Subscription> = self.subscriptions.keys().copied().collect();
+        subscriptions.sort();
+        subscriptions
+    }
+
+    pub fn is_subscribed(&self, publisher : MarketKind, subscriber : MarketKind) -> bool {
+        self.subscriptions.contains_key(&(publisher, subscriber))
+    }
+}
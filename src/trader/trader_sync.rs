@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+use market_common::event::event::{Event, EventKind};
+use market_common::good::good::Good;
+use market_common::good::good_kind::GoodKind;
+use market_common::good::good_kind::GoodKind::*;
+use market_common::market::Market;
+
+use crate::trader::{DEFAULT_TRANSACTION_AMOUNT, MarketKind, TRADER_NAME};
+use crate::trader::trader_errors::{TraderDemandError, TraderSupplyError};
+use crate::trader::trader_quotes::{MarketQuote, QuoteSnapshot};
+
+pub type MutexMarket = Arc<Mutex<dyn Market + Send>>;
+pub type RwLockMarket = Arc<RwLock<dyn Market + Send + Sync>>;
+
+//Anything that gives shared, thread-safe access to a market.
+//A poisoned lock is not a reason to stop trading: the market state is still there, so we keep using it.
+pub trait SharedMarket: Clone + Send + Sync {
+    fn read<R>(&self, f : impl FnOnce(&dyn Market) -> R) -> R;
+    fn write<R>(&self, f : impl FnOnce(&mut dyn Market) -> R) -> R;
+}
+
+impl SharedMarket for MutexMarket {
+    fn read<R>(&self, f : impl FnOnce(&dyn Market) -> R) -> R {
+        let guard = self.lock().unwrap_or_else(|e| e.into_inner());
+        f(&*guard)
+    }
+
+    fn write<R>(&self, f : impl FnOnce(&mut dyn Market) -> R) -> R {
+        let mut guard = self.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut *guard)
+    }
+}
+
+//with a RwLock the quote scans don't block each other
+impl SharedMarket for RwLockMarket {
+    fn read<R>(&self, f : impl FnOnce(&dyn Market) -> R) -> R {
+        let guard = RwLock::read(self).unwrap_or_else(|e| e.into_inner());
+        f(&*guard)
+    }
+
+    fn write<R>(&self, f : impl FnOnce(&mut dyn Market) -> R) -> R {
+        let mut guard = RwLock::write(self).unwrap_or_else(|e| e.into_inner());
+        f(&mut *guard)
+    }
+}
+
+//A Send + Sync flavour of the Trader. It only supports the core operations (quotes, buy, sell, wait, run):
+//the rest of the Trader features rely on Rc<RefCell<dyn Market>> and stay there.
+//The markets from the bose/bfb/tase crates are built as Rc<RefCell<dyn Market>>: run them on their own thread
+//with ThreadedMarket::spawn(...).shared() to use them here.
+pub struct SyncTrader<H : SharedMarket = MutexMarket> {
+    strategy: Box<dyn Fn(&mut SyncTrader<H>) + Send + Sync>,
+    owned_goods: HashMap<GoodKind, Good>,
+    markets: HashMap<MarketKind, H>,
+}
+
+impl<H : SharedMarket> SyncTrader<H> {
+
+    pub fn new() -> Self {
+        let mut owned_goods = HashMap::new();
+        owned_goods.insert(EUR, Good::new(EUR, 1000.0));
+        owned_goods.insert(USD, Good::new(USD, 0.0));
+        owned_goods.insert(YEN, Good::new(YEN, 0.0));
+        owned_goods.insert(YUAN, Good::new(YUAN, 0.0));
+
+        SyncTrader {
+            strategy: Box::new(|_| {}),
+            owned_goods,
+            markets: HashMap::new(),
+        }
+    }
+
+    pub fn with_market(mut self, kind : MarketKind, market : H) -> Self {
+        self.markets.insert(kind, market);
+        self
+    }
+
+    pub fn with_initial_money(self, money : f32) -> Self {
+        self.with_good(EUR, money)
+    }
+
+    pub fn with_good(mut self, good : GoodKind, qty : f32) -> Self {
+        self.owned_goods.insert(good, Good::new(good, qty));
+        self
+    }
+
+    pub fn set_strategy(&mut self, function : impl Fn(&mut SyncTrader<H>) + Send + Sync + 'static) {
+        self.strategy = Box::new(function);
+    }
+
+    pub fn run(&mut self, iterations : i32) {
+        for _ in 0..iterations {
+            let strategy = std::mem::replace(&mut self.strategy, Box::new(|_| {}));
+            strategy(self);
+            self.strategy = strategy;
+        }
+    }
+
+    pub fn get_market(&self, market : MarketKind) -> Result<H, TraderSupplyError> {
+        self.markets.get(&market).cloned().ok_or(TraderSupplyError::MarketNotFound)
+    }
+
+    pub fn get_owned_good_qty(&self, kind : GoodKind) -> f32 {
+        self.owned_goods.get(&kind).map_or(0.0, |g| g.get_qty())
+    }
+
+    pub fn get_capital(&self) -> f32 {
+        self.owned_goods.values().map(|g| g.get_qty() / g.get_kind().get_default_exchange_rate()).sum()
+    }
+
+    pub fn get_supply_price_qt(&self, market : MarketKind, kind : GoodKind, quantity : f32) -> Result<f32, TraderSupplyError> {
+        Ok(self.get_market(market)?.read(|m| m.get_buy_price(kind, quantity))?)
+    }
+
+    pub fn get_demand_price_qt(&self, market : MarketKind, kind : GoodKind, quantity : f32) -> Result<f32, TraderDemandError> {
+        Ok(self.get_market(market)?.read(|m| m.get_sell_price(kind, quantity))?)
+    }
+
+    //the market stays locked between the lock and the purchase, so no other thread can sneak in between
+    pub fn buy(&mut self, market : MarketKind, kind : GoodKind, amount : f32) -> Result<f32, TraderSupplyError> {
+        let price = self.get_supply_price_qt(market, kind, amount)?;
+        let handle = self.get_market(market)?;
+        let euros = self.owned_goods.get_mut(&EUR).expect("Euros disappeard from the trader's internal hashmap. Panic!");
+
+        let bought_goods = handle.write(|m| -> Result<Good, TraderSupplyError> {
+            let token = m.lock_buy(kind, amount, price, TRADER_NAME.to_string())?;
+            Ok(m.buy(token, euros)?)
+        })?;
+        let value = bought_goods.get_qty();
+
+        self.owned_goods.entry(kind).or_insert_with(|| Good::new(kind, 0.0))
+            .merge(bought_goods).expect("Couldn't add the bought goods to the trader's internal hashmap. Panic!");
+        Ok(value)
+    }
+
+    pub fn sell(&mut self, market : MarketKind, kind : GoodKind, amount : f32) -> Result<f32, TraderDemandError> {
+        let price = self.get_demand_price_qt(market, kind, amount)?;
+        let handle = self.get_market(market)?;
+        let goods = self.owned_goods.get_mut(&kind).ok_or(TraderDemandError::TraderInsufficientGoods)?;
+
+        let sold_goods = handle.write(|m| -> Result<Good, TraderDemandError> {
+            let token = m.lock_sell(kind, amount, price, TRADER_NAME.to_string())?;
+            Ok(m.sell(token, goods)?)
+        })?;
+        let value = sold_goods.get_qty();
+
+        self.owned_goods.get_mut(&EUR).expect("Euros disappeard from the trader's internal hashmap. Panic!")
+            .merge(sold_goods).expect("Couldn't add the sold goods to the trader's internal hashmap. Panic!");
+        Ok(value)
+    }
+
+    pub fn wait(&mut self) {
+        for market in self.markets.values() {
+            market.write(|m| m.on_event(Event {
+                kind: EventKind::Wait,
+                good_kind: EUR,
+                quantity: 0.0,
+                price: 0.0,
+            }));
+        }
+    }
+
+    pub fn wait_for(&mut self, days : u32) {
+        for _ in 0..days {
+            self.wait();
+        }
+    }
+
+    pub fn quote_snapshot(&self, kind : GoodKind, quantity : f32) -> QuoteSnapshot {
+        let quotes = self.markets.iter()
+            .map(|(marketkind, market)| market.read(|m| QuoteSnapshot::quote_market(*marketkind, m, kind, quantity)))
+            .collect();
+        QuoteSnapshot::from_quotes(kind, quantity, quotes)
+    }
+
+    //same as quote_snapshot(), but every market is asked on its own thread. A market that panics takes the caller with it,
+    //as it would in quote_snapshot().
+    pub fn parallel_quote_snapshot(&self, kind : GoodKind, quantity : f32) -> QuoteSnapshot {
+        let quotes: Vec<MarketQuote> = thread::scope(|scope| {
+            let handles: Vec<_> = self.markets.iter()
+                .map(|(marketkind, market)| scope.spawn(move || market.read(|m| QuoteSnapshot::quote_market(*marketkind, m, kind, quantity))))
+                .collect();
+            handles.into_iter()
+                .map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+                .collect()
+        });
+        QuoteSnapshot::from_quotes(kind, quantity, quotes)
+    }
+
+    pub fn cheapest_supplier(&self, kind : GoodKind) -> Option<MarketKind> {
+        self.parallel_quote_snapshot(kind, DEFAULT_TRANSACTION_AMOUNT).cheapest_supplier()
+    }
+
+    pub fn best_buyer(&self, kind : GoodKind) -> Option<MarketKind> {
+        self.parallel_quote_snapshot(kind, DEFAULT_TRANSACTION_AMOUNT).best_buyer()
+    }
+}
+
+impl<H : SharedMarket> Default for SyncTrader<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//Runs independent simulations, one per seed, and returns their results in the same order.
+//They are shared among as many worker threads as the machine can run at once (never more than the seeds).
+//Every simulation builds its own markets and trader inside its thread, so this works for the Rc-based Trader too.
+//If a simulation panics, the panic is passed on to the caller.
+pub fn run_in_parallel<R : Send>(seeds : &[u64], simulation : impl Fn(u64) -> R + Sync) -> Vec<R> {
+    let workers = thread::available_parallelism().map_or(1, |n| n.get()).min(seeds.len());
+    let next = AtomicUsize::new(0);
+    let (simulation, next) = (&simulation, &next);
+
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers).map(|_| scope.spawn(move || {
+            let mut done = Vec::new();
+            loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                match seeds.get(index) {
+                    Some(seed) => done.push((index, simulation(*seed))),
+                    None => return done,
+                }
+            }
+        })).collect();
+        handles.into_iter()
+            .flat_map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    });
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}